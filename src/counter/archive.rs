@@ -0,0 +1,258 @@
+extern crate tar;
+extern crate zip;
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use super::decompress::decompress;
+use super::error::{with_path, Error};
+use super::{Count, Metrics};
+
+/// The counts for a regular file inside of an archive, either a `Count` or the `Metrics` it was
+/// counted for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member<T = Count> {
+    /// The path of the file inside of the archive.
+    pub path: PathBuf,
+    pub count: T,
+}
+
+impl<T> Member<T> {
+    /// Return the name to print for the member, `archive:path`.
+    pub fn label<P: AsRef<Path>>(&self, archive: P) -> PathBuf {
+        label(archive.as_ref(), &self.path)
+    }
+}
+
+/// The counts for a member of an archive, or the error for it. Errors are labeled like
+/// `Member::label()`, except for ones that stop the rest of the archive from being read, which
+/// have the path of the archive.
+pub type MemberResult<T = Count> = Result<Member<T>, Error>;
+
+fn label(archive: &Path, member: &Path) -> PathBuf {
+    let mut label = OsString::from(archive);
+    label.push(":");
+    label.push(member);
+    PathBuf::from(label)
+}
+
+/// Return `true` if the file name looks like an archive that `Metrics::count_archive()` can read:
+/// `.tar` (optionally compressed with gzip, bzip2, xz, or zstd), `.tgz`, or `.zip`.
+pub fn is_archive<P: AsRef<Path>>(file: P) -> bool {
+    const EXTENSIONS: &[&str] = &[".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tar.xz", ".tar.zst", ".zip"];
+    let file = file.as_ref().to_string_lossy().to_ascii_lowercase();
+    EXTENSIONS.iter().any(|extension| file.ends_with(extension))
+}
+
+impl Count {
+    /// Generate counts for each regular file inside of the given archive, like
+    /// `Metrics::count_archive()`.
+    pub fn from_archive<P: AsRef<Path>>(file: P) -> Result<Vec<MemberResult>, Error> {
+        let members = Metrics::new().count_archive(file)?;
+        Ok(members.into_iter()
+            .map(|member| member.map(|member| Member { path: member.path, count: member.count.count() }))
+            .collect())
+    }
+}
+
+impl Metrics {
+    /// Count each regular file inside of the given archive for this set of metrics, in the order
+    /// they are stored. Directories, links, and other special entries are skipped.
+    ///
+    /// A member that can't be read doesn't stop the others from being counted. The error is only
+    /// returned if the archive itself can't be opened.
+    pub fn count_archive<P: AsRef<Path>>(&self, file: P) -> Result<Vec<MemberResult<Metrics>>, Error> {
+        let file = file.as_ref();
+        with_path(file, || {
+            if file.to_string_lossy().to_ascii_lowercase().ends_with(".zip") { from_zip(file, self) }
+            else { from_tar(file, self) }
+        })
+    }
+}
+
+fn from_tar(file: &Path, metrics: &Metrics) -> Result<Vec<MemberResult<Metrics>>, Error> {
+    let archive_path = file;
+    let file = File::open(file)?;
+    let reader = decompress(BufReader::new(file)).map_err(|e| Error::from_io(e, Count::new()))?;
+    let mut archive = tar::Archive::new(reader);
+    let mut members = Vec::new();
+    for entry in archive.entries()? {
+        // the entries after a header that can't be read can't be found
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                members.push(Err(Error::from_io(e, Count::new()).with_path(archive_path)));
+                break;
+            }
+        };
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = match entry.path() {
+            Ok(path) => path.into_owned(),
+            Err(e) => {
+                let path = PathBuf::from(String::from_utf8_lossy(&entry.path_bytes()).into_owned());
+                members.push(Err(Error::from_io(e, Count::new()).with_path(label(archive_path, &path))));
+                continue;
+            }
+        };
+        members.push(match metrics.count_buf_read(BufReader::new(entry)) {
+            Ok(count) => Ok(Member { path, count }),
+            Err(e) => Err(e.with_path(label(archive_path, &path))),
+        });
+    }
+    Ok(members)
+}
+
+fn from_zip(file: &Path, metrics: &Metrics) -> Result<Vec<MemberResult<Metrics>>, Error> {
+    let archive_path = file;
+    let file = File::open(file)?;
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))
+        .map_err(|e| Error::from_io(io::Error::from(e), Count::new()))?;
+    let mut members = Vec::new();
+    for i in 0..archive.len() {
+        let path = PathBuf::from(archive.name_for_index(i).unwrap_or_default());
+        let entry = match archive.by_index(i) {
+            Ok(entry) => entry,
+            // an entry that can't be opened is skipped if its name says it is a directory
+            Err(_) if path.to_string_lossy().ends_with('/') => continue,
+            Err(e) => {
+                members.push(Err(Error::from_io(io::Error::from(e), Count::new())
+                    .with_path(label(archive_path, &path))));
+                continue;
+            }
+        };
+        if !entry.is_file() {
+            continue;
+        }
+        members.push(match metrics.count_buf_read(BufReader::new(entry)) {
+            Ok(count) => Ok(Member { path, count }),
+            Err(e) => Err(e.with_path(label(archive_path, &path))),
+        });
+    }
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_archive, tar, zip, Member};
+    use counter::decompress::tests::compress;
+    use counter::{Compression, Count};
+    use std::fs;
+    use std::io::prelude::*;
+    use std::path::PathBuf;
+    use test_util::Tree;
+
+    const FILES: &[(&str, &str)] = &[("src/main.rs", "fn main() {\n}\n"), ("README", "one two three\n")];
+
+    fn expected() -> Vec<Member> {
+        FILES.iter()
+            .map(|&(path, text)| {
+                let mut count = Count::new();
+                count.newlines = text.matches('\n').count() as u64;
+                count.words = text.split_whitespace().count() as u64;
+                count.bytes = text.len() as u64;
+                count.chars = text.len() as u64;
+                count.max_line = text.lines().map(str::len).max().unwrap() as u64;
+                Member { path: PathBuf::from(path), count }
+            })
+            .collect()
+    }
+
+    fn tar() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        builder.append_data(&mut header, "src/", &[][..]).unwrap();
+        for &(path, text) in FILES {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(text.len() as u64);
+            builder.append_data(&mut header, path, text.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn zip() -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(::std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        writer.add_directory("src/", options).unwrap();
+        for &(path, text) in FILES {
+            writer.start_file(path, options).unwrap();
+            writer.write_all(text.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn archive_names() {
+        assert!(is_archive("release.tar"));
+        assert!(is_archive("release.tar.gz"));
+        assert!(is_archive("release.TGZ"));
+        assert!(is_archive("release.tar.zst"));
+        assert!(is_archive("release.zip"));
+        assert!(!is_archive("release.gz"));
+        assert!(!is_archive("tar"));
+    }
+
+    #[test]
+    fn members() {
+        let tree = Tree::new("archive-members", &[]);
+        fs::create_dir_all(&tree.root).unwrap();
+        let archives = vec![
+            ("a.tar", tar()),
+            ("a.tar.gz", compress(Compression::Gzip, &tar())),
+            ("a.tar.xz", compress(Compression::Xz, &tar())),
+            ("a.zip", zip()),
+        ];
+        for (name, bytes) in archives {
+            fs::write(tree.path(name), bytes).unwrap();
+            let members: Result<Vec<Member>, _> = Count::from_archive(tree.path(name)).unwrap().into_iter().collect();
+            assert_eq!(members.unwrap(), expected(), "{}", name);
+        }
+    }
+
+    /// A member that can't be read is an error for that member only.
+    #[test]
+    fn member_errors() {
+        let mut writer = zip::ZipWriter::new(::std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for &(path, text) in FILES {
+            writer.start_file(path, options).unwrap();
+            writer.write_all(text.as_bytes()).unwrap();
+        }
+        let mut bytes = writer.finish().unwrap().into_inner();
+
+        // change the contents of the first file so its checksum doesn't match
+        let text = FILES[0].1.as_bytes();
+        let offset = bytes.windows(text.len()).position(|w| w == text).unwrap();
+        bytes[offset] = b'F';
+
+        let tree = Tree::new("archive-member-errors", &[]);
+        fs::create_dir_all(&tree.root).unwrap();
+        fs::write(tree.path("a.zip"), bytes).unwrap();
+        let mut members = Count::from_archive(tree.path("a.zip")).unwrap().into_iter();
+
+        let error = members.next().unwrap().unwrap_err();
+        assert_eq!(error.path(), PathBuf::from(tree.path("a.zip:src/main.rs")));
+        assert!(error.counts().is_some());
+        assert_eq!(members.next().unwrap().unwrap(), expected()[1]);
+        assert!(members.next().is_none());
+    }
+
+    #[test]
+    fn labels() {
+        let member = Member { path: PathBuf::from("src/main.rs"), count: Count::new() };
+        assert_eq!(member.label("release.tar"), PathBuf::from("release.tar:src/main.rs"));
+    }
+
+    #[test]
+    fn errors() {
+        let tree = Tree::new("archive-errors", &["broken.zip"]);
+        assert!(Count::from_archive(tree.path("broken.zip")).is_err());
+        assert!(Count::from_archive(tree.path("missing.tar")).is_err());
+    }
+}
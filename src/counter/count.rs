@@ -1,13 +1,37 @@
+extern crate memmap2;
+
 use std::io;
 use std::io::prelude::*;
 use std::io::{stdin, BufReader};
-use std::fs::File;
+use std::fs::{File, Metadata};
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use super::display::{Display, Json};
+use super::decompress::decompress;
+use super::error::{with_path, Error};
+use super::kernel;
+use super::{Counter, Metrics};
+use super::Span;
+use pool;
+use pool::Budget;
 use std::cmp::max;
-use std::error::Error;
-use super::display::Display;
 use options::Options;
-use std::mem;
+use self::memmap2::Mmap;
+
+/// The smallest chunk `from_slice_chunked()` will hand to a thread.
+const MIN_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The buffer size `from_reader()` uses.
+const BUFFER_SIZE: usize = 64 * 1024;
 
+/// The block size to use when a file system doesn't say.
+const DEFAULT_BLOCK_SIZE: u64 = 512;
+
+/// How long a file has to go unmodified before `from_mmap_chunked()` maps it.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Count {
     pub newlines: u64,
     pub words: u64,
@@ -28,144 +52,361 @@ impl Count {
     }
 
     /// Return a Count with only the number of bytes in the given file.
-    pub fn bytes_from_file(file: &str) -> Result<Count, Box<Error>> {
-        // read a single byte from the file to detect errors
-        let mut buf = [0u8; 1];
-        let mut file = try!(File::open(file));
-        try!(file.read(&mut buf));
+    ///
+    /// The size of a regular file is used to skip most of it, but the end is always read. Pipes,
+    /// devices, and pseudo files (like the ones in `/proc` and `/sys`, which report a size of zero
+    /// or of a whole block) are read in full.
+    pub fn bytes_from_file<P: AsRef<Path>>(file: P) -> Result<Count, Error> {
+        let file = file.as_ref();
+        with_path(file, || {
+            let mut file = File::open(file)?;
+            let metadata = file.metadata()?;
+            let mut count = Self::new();
+            if metadata.is_file() {
+                count.bytes = skip_to_end(&mut file, &metadata).map_err(|e| Error::from_io(e, count))?;
+            }
+            count.bytes += io::copy(&mut file, &mut io::sink()).map_err(|e| Error::from_io(e, count))?;
+            Ok(count)
+        })
+    }
 
-        let mut count = Self::new();
-        count.bytes = try!(file.metadata()).len();
-        Ok(count)
+    /// Return a Count with the number of lines and bytes in the given file, like
+    /// `lines_from_file()`, but map the file into memory and count the newlines in large files in
+    /// parallel with the jobs in `budget`. Files are mapped like `from_mmap()`.
+    pub fn lines_from_mmap_chunked<P: AsRef<Path>>(file: P, budget: &Budget) -> Result<Count, Error> {
+        let path = file.as_ref();
+        with_path(path, || match map(path)? {
+            Mapped::Map(map) => Ok(Count::lines_from_slice_chunked(&map, budget)),
+            Mapped::Read(file) => Count::lines_from_buf_read(BufReader::new(file)),
+        })
     }
 
     /// Return a Count with the number of lines and bytes in the given file.
-    pub fn lines_from_file(file: &str) -> Result<Count, Box<Error>> {
-        let file = try!(File::open(file));
+    pub fn lines_from_file<P: AsRef<Path>>(file: P) -> Result<Count, Error> {
+        let file = file.as_ref();
+        with_path(file, || Count::lines_from_buf_read(BufReader::new(File::open(file)?)))
+    }
 
+    /// Return a Count with the number of lines and bytes in the given reader.
+    fn lines_from_buf_read<R: BufRead>(mut reader: R) -> Result<Count, Error> {
         let mut count = Self::new();
-        count.bytes = try!(file.metadata()).len();
-
-        let mut file = BufReader::new(file);
         loop {
-            let mut buf = [0u8; 8]; // size of u64
-            if try!(file.read(&mut buf)) == 0 {
+            let len = {
+                let buf = match reader.fill_buf() {
+                    Ok(buf) => buf,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(Error::from_io(e, count)),
+                };
+                count.newlines += kernel::newlines(buf);
+                count.bytes += buf.len() as u64;
+                buf.len()
+            };
+            if len == 0 {
                 break;
             }
-
-            // AND the entire 8 byte buffer with a mask of \n bytes to see if there are any
-            // newlines in the buffer. If there are we search for them, if not, we skip the search
-            // all together.
-            let has_newlines = unsafe { 0x0a0a0a0a0a0a0a0a & mem::transmute::<_, u64>(buf) };
-            if has_newlines != 0 {
-                for b in &buf {
-                    if *b == 0x0a { count.newlines += 1; }
-                }
-            }
+            reader.consume(len);
         }
 
         Ok(count)
     }
 
 
-    pub fn from_file(file: &str) -> Result<Count, Box<Error>> {
-        let file = try!(File::open(file));
-        let reader = BufReader::new(file);
-        Count::from_iter(reader.bytes())
+    pub fn from_file<P: AsRef<Path>>(file: P) -> Result<Count, Error> {
+        let file = file.as_ref();
+        with_path(file, || Count::from_reader(File::open(file)?))
     }
 
-    pub fn from_stdin() -> Result<Count, Box<Error>> {
-        let stdin = stdin();
-        let stdin = stdin.lock();
-        let stdin = BufReader::new(stdin);
-        let stdin = stdin.bytes();
-        Count::from_iter(stdin)
+    /// Generate counts for the given file by mapping it into memory.
+    ///
+    /// Only regular files with a non-zero size are mapped. Pipes, devices, directories, and
+    /// pseudo files (like the ones in `/proc`, which report a size of zero) are read with a
+    /// `BufReader` instead, exactly like `from_file`, so errors for those are reported the same
+    /// way. So are files that were modified in the last couple of seconds, since a file that is
+    /// still being written to (like a log) could be truncated while it is mapped.
+    pub fn from_mmap<P: AsRef<Path>>(file: P) -> Result<Count, Error> {
+        Count::from_mmap_chunked(file, &Budget::new(1))
     }
 
-    /// Generate newline, word, character, byte, and maximum line length counts for the given
-    /// iterator over a set of bytes. A word is a non-zero-length sequence of characters delimited
-    /// by white space.
-    fn from_iter<I>(bytes: I) -> Result<Count, Box<Error>>
-        where I: Iterator<Item=io::Result<u8>>
-    {
-        enum State {
-            Whitespace,
-            Word,
+    /// Generate counts for the given file like `from_mmap`, but split large files into chunks and
+    /// count them in parallel with the jobs in `budget`.
+    pub fn from_mmap_chunked<P: AsRef<Path>>(file: P, budget: &Budget) -> Result<Count, Error> {
+        Metrics::new().count_file(file, budget).map(|metrics| metrics.count())
+    }
+
+    pub fn from_stdin() -> Result<Count, Error> {
+        Metrics::new().count_stdin().map(|metrics| metrics.count())
+    }
+
+    /// Generate counts for everything in the given reader, like a `Cursor`, a socket, or a
+    /// decompressor. Reads are buffered, so the reader doesn't need to be.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Count, Error> {
+        Count::from_reader_with_capacity(BUFFER_SIZE, reader)
+    }
+
+    /// Generate counts for everything in the given reader, reading up to `capacity` bytes at a
+    /// time.
+    pub fn from_reader_with_capacity<R: Read>(capacity: usize, reader: R) -> Result<Count, Error> {
+        Count::from_buf_read(BufReader::with_capacity(capacity, reader))
+    }
+
+    /// Generate counts for the decompressed contents of the given file. The compression format is
+    /// detected from the first bytes, and files that aren't compressed are counted as they are.
+    pub fn from_file_decompressed<P: AsRef<Path>>(file: P) -> Result<Count, Error> {
+        Metrics::new().count_file_decompressed(file).map(|metrics| metrics.count())
+    }
+
+    /// Generate counts for the decompressed contents of stdin, like `from_file_decompressed()`.
+    pub fn from_stdin_decompressed() -> Result<Count, Error> {
+        Metrics::new().count_stdin_decompressed().map(|metrics| metrics.count())
+    }
+
+    /// Generate counts for a slice of bytes that is already in memory.
+    fn from_slice(bytes: &[u8]) -> Count {
+        let mut counter = Counter::new();
+        counter.update(bytes);
+        counter.finish()
+    }
+
+    /// Generate counts for a slice of bytes by splitting it into chunks and counting them in
+    /// parallel with the jobs in `budget`. Small slices are counted in one go.
+    fn from_slice_chunked(bytes: &[u8], budget: &Budget) -> Count {
+        let chunks = chunks(bytes, budget);
+        if chunks.len() <= 1 {
+            return Count::from_slice(bytes);
         }
 
+        let mut span = Span::new();
+        pool::ordered(&chunks, budget, |chunk| Span::from_slice(chunk), |_, next| span = span + next);
+        span.count
+    }
+
+    /// Return a Count with the number of lines and bytes in a slice, counting the newlines in
+    /// chunks like `from_slice_chunked()`.
+    fn lines_from_slice_chunked(bytes: &[u8], budget: &Budget) -> Count {
         let mut count = Count::new();
-        let mut current_line_length = 0;
+        count.bytes = bytes.len() as u64;
+        pool::ordered(&chunks(bytes, budget), budget, |chunk| kernel::newlines(chunk), |_, newlines| {
+            count.newlines += newlines;
+        });
+        count
+    }
 
-        let mut state = State::Whitespace;
-        for c in bytes {
-            let c_byte = try!(c);
-            count.bytes += 1;
+    /// Generate counts for everything in the given reader, one buffer at a time. The reader's
+    /// own buffer is used as is, so its size decides how much is read at once.
+    ///
+    /// If a read fails partway through, the error has the counts for everything before it.
+    pub fn from_buf_read<R: BufRead>(reader: R) -> Result<Count, Error> {
+        Metrics::new().count_buf_read(reader).map(|metrics| metrics.count())
+    }
 
-            let c = c_byte as char;
-            if c == '\n' {
-                count.newlines += 1;
-                current_line_length = 0;
-            }
-            else {
-                current_line_length += 1;
-                count.max_line = max(count.max_line, current_line_length);
-            }
+    /// Combine the counts for another input into this one, for building totals.
+    ///
+    /// Newlines, words, bytes, and characters are summed. The longest line of the total is the
+    /// longest line of any of the inputs.
+    pub fn aggregate(&mut self, other: &Count) {
+        self.newlines += other.newlines;
+        self.words += other.words;
+        self.bytes += other.bytes;
+        self.chars += other.chars;
+        self.max_line = max(self.max_line, other.max_line);
+    }
 
-            state = match state {
-                State::Whitespace if !c.is_whitespace() => {
-                    count.words += 1;
-                    State::Word
-                }
-                State::Word if c.is_whitespace() => State::Whitespace,
-                state => state
-            };
+    pub fn display<'a>(&'a self, opts: &'a Options) -> Display<'a> {
+        Display::new(&Metrics::from_count(self, opts), opts)
+    }
 
-            // count utf8 single bytes and leading bytes, ignore continuation bytes
-            if c_byte & 0b1100_0000 == 0b1000_0000 {
-                // utf8 continuation byte, ignore it
-            }
-            else {
-                // utf8 sigle byte or leading byte, count it
-                count.chars += 1;
+    pub fn json<'a>(&'a self, opts: &'a Options) -> Json<'a> {
+        Json::new(opts).count(self)
+    }
+}
+
+/// Counting an input for a set of metrics. Each of these uses `self` as the prototype and returns
+/// a new set with the same metrics (see `Metrics::empty()`), so the sets for all of the inputs can
+/// be merged into a total. The standard counts are counted once for the whole set, the same way
+/// as the `Count` functions.
+impl Metrics {
+    /// Count the given file, mapping it into memory and splitting large files into chunks like
+    /// `Count::from_mmap_chunked()`. The registered metrics are given the whole file at once.
+    pub fn count_file<P: AsRef<Path>>(&self, file: P, budget: &Budget) -> Result<Metrics, Error> {
+        let path = file.as_ref();
+        with_path(path, || match map(path)? {
+            Mapped::Map(map) => Ok(self.count_slice(&map, budget)),
+            Mapped::Read(file) => self.count_reader(file),
+        })
+    }
+
+    pub fn count_stdin(&self) -> Result<Metrics, Error> {
+        let stdin = stdin();
+        let stdin = stdin.lock();
+        with_path(Path::new("-"), || self.count_reader(stdin))
+    }
+
+    /// Count everything in the given reader, like `Count::from_reader()`.
+    pub fn count_reader<R: Read>(&self, reader: R) -> Result<Metrics, Error> {
+        self.count_buf_read(BufReader::with_capacity(BUFFER_SIZE, reader))
+    }
+
+    /// Count the decompressed contents of the given file, like `Count::from_file_decompressed()`.
+    pub fn count_file_decompressed<P: AsRef<Path>>(&self, file: P) -> Result<Metrics, Error> {
+        let file = file.as_ref();
+        with_path(file, || {
+            let file = File::open(file)?;
+            let reader = decompress(BufReader::new(file)).map_err(|e| Error::from_io(e, Count::new()))?;
+            self.count_buf_read(reader)
+        })
+    }
+
+    /// Count the decompressed contents of stdin, like `count_file_decompressed()`.
+    pub fn count_stdin_decompressed(&self) -> Result<Metrics, Error> {
+        let stdin = stdin();
+        let stdin = stdin.lock();
+        with_path(Path::new("-"), || {
+            let reader = decompress(BufReader::new(stdin)).map_err(|e| Error::from_io(e, Count::new()))?;
+            self.count_buf_read(reader)
+        })
+    }
+
+    /// Count everything in the given reader, one buffer at a time, like `Count::from_buf_read()`.
+    ///
+    /// If a read fails partway through, the error has the standard counts for everything before
+    /// it. The values of the registered metrics are lost.
+    pub fn count_buf_read<R: BufRead>(&self, mut reader: R) -> Result<Metrics, Error> {
+        let mut metrics = self.empty();
+        loop {
+            let len = {
+                let buf = match reader.fill_buf() {
+                    Ok(buf) => buf,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(Error::from_io(e, metrics.count())),
+                };
+                metrics.update(buf);
+                buf.len()
+            };
+            if len == 0 {
+                break;
             }
+            reader.consume(len);
         }
 
-        Ok(count)
+        Ok(metrics)
     }
 
-    pub fn display<'a>(&'a self, opts: &'a Options) -> Display {
-        Display::new(self, opts)
+    /// Count a slice of bytes, with the standard counts split into chunks like
+    /// `Count::from_slice_chunked()`.
+    fn count_slice(&self, bytes: &[u8], budget: &Budget) -> Metrics {
+        let mut metrics = self.empty();
+        metrics.add_count(&Count::from_slice_chunked(bytes, budget));
+        metrics.update_metrics(bytes);
+        metrics
+    }
+}
+
+/// Seek close to the end of a regular file and return the position, so only the rest has to be
+/// read. Like GNU wc, the position is always more than a block from the end. A pseudo file that
+/// reports a size of zero or of one block is read from the start, and a file that grew since it
+/// was stat-ed is read to its real end.
+fn skip_to_end(file: &mut File, metadata: &Metadata) -> io::Result<u64> {
+    let size = metadata.len();
+    file.seek(SeekFrom::Start(size - size % (block_size(metadata) + 1)))
+}
+
+/// A file opened by `map()`, either mapped into memory or to be read.
+enum Mapped {
+    Map(Mmap),
+    /// The file that was opened, so it is read without opening it again. A FIFO can only be
+    /// opened once for the data its writer sends.
+    Read(File),
+}
+
+/// Open and map the given file, or return the open file if it should be read instead.
+///
+/// Only regular files with a non-zero size are mapped, and only once they have settled (see
+/// `is_settled()`). If the file can't be mapped for whatever reason, it is read instead too.
+fn map(file: &Path) -> Result<Mapped, Error> {
+    let file = File::open(file)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.len() == 0 || !is_settled(&metadata, SystemTime::now()) {
+        return Ok(Mapped::Read(file));
+    }
+
+    // SAFETY: the map is only read, and it is never handed out beyond the counting functions. If
+    // another process truncates the file while it is mapped, reading the missing pages raises
+    // SIGBUS, which can't be prevented from here. Files that are still changing are read instead
+    // (see `is_settled()`), which leaves files that are truncated without being written to first,
+    // the same trade-off other tools that map files make.
+    match unsafe { Mmap::map(&file) } {
+        Ok(map) => Ok(Mapped::Map(map)),
+        Err(_) => Ok(Mapped::Read(file)),
+    }
+}
+
+/// Split a slice into about one chunk per job in `budget`, but no smaller than `MIN_CHUNK_SIZE`.
+fn chunks<'a>(bytes: &'a [u8], budget: &Budget) -> Vec<&'a [u8]> {
+    let chunk_size = max(bytes.len().div_ceil(budget.jobs()), MIN_CHUNK_SIZE);
+    bytes.chunks(chunk_size).collect()
+}
+
+/// Return `true` if the file hasn't been modified for `SETTLE_TIME`, so it isn't likely to be
+/// truncated while it is mapped. Files without a modification time are never settled.
+fn is_settled(metadata: &Metadata, now: SystemTime) -> bool {
+    match metadata.modified().map(|modified| now.duration_since(modified)) {
+        Ok(Ok(age)) => age >= SETTLE_TIME,
+        _ => false,
+    }
+}
+
+/// The preferred I/O block size for the file.
+#[cfg(unix)]
+fn block_size(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    match metadata.blksize() {
+        0 => DEFAULT_BLOCK_SIZE,
+        size => size,
+    }
+}
+
+#[cfg(not(unix))]
+fn block_size(_: &Metadata) -> u64 {
+    DEFAULT_BLOCK_SIZE
+}
+
+impl Default for Count {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 use std::ops::Add;
 impl Add for Count {
     type Output = Count;
-    fn add(self, rhs: Self) -> Self::Output {
-        Count {
-            newlines: self.newlines + rhs.newlines,
-            words: self.words + rhs.words,
-            bytes: self.bytes + rhs.bytes,
-            chars: self.chars + rhs.chars,
-            max_line: self.max_line + rhs.max_line,
-        }
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self.aggregate(&rhs);
+        self
+    }
+}
+
+use std::iter::Sum;
+impl<'a> Sum<&'a Count> for Count {
+    fn sum<I: Iterator<Item=&'a Count>>(iter: I) -> Self {
+        iter.fold(Count::new(), |mut total, count| {
+            total.aggregate(count);
+            total
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::io;
+    use counter::metric::tests::BlankLines;
+    use counter::Metrics;
+    use options::Options;
+    use pool::Budget;
     use super::Count;
 
-    fn vec_from_string(s: &str) -> Vec<io::Result<u8>> {
-        s.bytes()
-            .map(|c| Ok(c))
-            .collect()
-    }
-
     #[test]
     fn one_word() {
-        let count = Count::from_iter(vec_from_string("word\n").into_iter()).unwrap();
+        let count = Count::from_reader("word\n".as_bytes()).unwrap();
         assert_eq!(count.newlines, 1);
         assert_eq!(count.words, 1);
         assert_eq!(count.bytes, 5);
@@ -175,7 +416,7 @@ mod tests {
 
     #[test]
     fn one_word_no_newline() {
-        let count = Count::from_iter(vec_from_string("word").into_iter()).unwrap();
+        let count = Count::from_reader("word".as_bytes()).unwrap();
         assert_eq!(count.newlines, 0);
         assert_eq!(count.words, 1);
         assert_eq!(count.bytes, 4);
@@ -185,7 +426,7 @@ mod tests {
 
     #[test]
     fn words_and_whitespace() {
-        let count = Count::from_iter(vec_from_string("   words and  \t\n  whitespace\n").into_iter()).unwrap();
+        let count = Count::from_reader("   words and  \t\n  whitespace\n".as_bytes()).unwrap();
         assert_eq!(count.newlines, 2);
         assert_eq!(count.words, 3);
         assert_eq!(count.bytes, 29);
@@ -195,21 +436,21 @@ mod tests {
 
     #[test]
     fn line_length() {
-        let count = Count::from_iter(vec_from_string(
+        let count = Count::from_reader(
 r"Testing out some long lines to see if it picks the largest one correctly.
 That last line was fairly long, but I think we can do better.
 Apparentlly not.
 Hahaha, just kidding! Of course we can do better. I can go on forever baby! Why don't we start with a list of my favorite movies. Back to the Future, The Last Dragon, Lock Stock and Two Smoking Barrels, Jurassic Park, Casablanca, Pulp Fiction, Forest Gump, City of God. I think that's enough.
 
 And a short line to end it.
-").into_iter()).unwrap();
+".as_bytes()).unwrap();
         assert_eq!(count.newlines, 6);
         assert_eq!(count.max_line, 292);
     }
 
     #[test]
     fn unicode() {
-        let count = Count::from_iter(vec_from_string("இঈஇ 💖\n").into_iter()).unwrap();
+        let count = Count::from_reader("இঈஇ 💖\n".as_bytes()).unwrap();
         assert_eq!(count.newlines, 1);
         assert_eq!(count.words, 2);
         assert_eq!(count.bytes, 15);
@@ -217,4 +458,272 @@ And a short line to end it.
         assert_eq!(count.max_line, 14);
     }
 
+    fn totals_input() -> Vec<Count> {
+        vec![
+            Count { newlines: 1, words: 2, bytes: 3, chars: 4, max_line: 50 },
+            Count { newlines: 10, words: 20, bytes: 30, chars: 40, max_line: 7 },
+            Count { newlines: 100, words: 200, bytes: 300, chars: 400, max_line: 12 },
+        ]
+    }
+
+    #[test]
+    fn totals() {
+        let mut total = Count::new();
+        for count in totals_input().iter() {
+            total.aggregate(count);
+        }
+        assert_eq!(total.newlines, 111);
+        assert_eq!(total.words, 222);
+        assert_eq!(total.bytes, 333);
+        assert_eq!(total.chars, 444);
+        assert_eq!(total.max_line, 50);
+    }
+
+    #[test]
+    fn totals_add_and_sum() {
+        let counts = totals_input();
+        let added = counts.iter().fold(Count::new(), |total, count| total + *count);
+        let summed: Count = counts.iter().sum();
+        assert_eq!(added, summed);
+        assert_eq!(summed.newlines, 111);
+        assert_eq!(summed.words, 222);
+        assert_eq!(summed.bytes, 333);
+        assert_eq!(summed.chars, 444);
+        assert_eq!(summed.max_line, 50);
+    }
+
+    #[test]
+    fn totals_empty() {
+        let summed: Count = Vec::new().iter().sum();
+        assert_eq!(summed, Count::new());
+    }
+
+    #[test]
+    fn chunked() {
+        let mut bytes = Vec::new();
+        while bytes.len() < 3 * super::MIN_CHUNK_SIZE {
+            bytes.extend("some words,  இঈஇ 💖\n\tand a longer line with more words in it\n".bytes());
+        }
+        let chunked = Count::from_slice_chunked(&bytes, &Budget::new(4));
+        assert_eq!(chunked, Count::from_slice(&bytes));
+        assert_eq!(chunked, Count::from_slice_chunked(&bytes, &Budget::new(1)));
+
+        let lines = Count::lines_from_slice_chunked(&bytes, &Budget::new(4));
+        assert_eq!((lines.newlines, lines.bytes), (chunked.newlines, chunked.bytes));
+        assert_eq!(lines, Count::lines_from_slice_chunked(&bytes, &Budget::new(1)));
+    }
+
+    /// Registered metrics are counted along with the standard counts, however the input is read.
+    #[test]
+    fn metrics() {
+        let mut bytes = Vec::new();
+        while bytes.len() < 3 * super::MIN_CHUNK_SIZE {
+            bytes.extend("some words\n\n  \nand more words\n".bytes());
+        }
+        let mut metrics = Metrics::standard(&Options::test_empty().unwrap());
+        metrics.register(Box::new(BlankLines::default()));
+
+        let chunked = metrics.count_slice(&bytes, &Budget::new(4));
+        let read = metrics.count_reader(&bytes[..]).unwrap();
+        assert_eq!(chunked.count(), Count::from_slice(&bytes));
+        assert_eq!(chunked.names(), vec!["lines", "words", "bytes", "blank_lines"]);
+        assert_eq!(chunked.iter().map(|value| value.value()).collect::<Vec<_>>(),
+                   read.iter().map(|value| value.value()).collect::<Vec<_>>());
+        assert_eq!(read.iter().last().unwrap().value(), 2 * chunked.count().newlines / 4);
+
+        let file = metrics.count_file("src/counter/count.rs", &Budget::new(2)).unwrap();
+        assert_eq!(file.count(), Count::from_file("src/counter/count.rs").unwrap());
+    }
+
+    #[test]
+    fn settled() {
+        use std::fs::{self, File};
+        use std::time::SystemTime;
+        use test_util::Tree;
+
+        let tree = Tree::new("count-settled", &["log"]);
+        let metadata = fs::metadata(tree.path("log")).unwrap();
+        let now = SystemTime::now();
+        assert!(!super::is_settled(&metadata, now));
+        assert!(super::is_settled(&metadata, now + super::SETTLE_TIME));
+
+        // a file that was modified in the future can't be trusted either
+        File::options().write(true).open(tree.path("log")).unwrap()
+            .set_modified(now + super::SETTLE_TIME * 2).unwrap();
+        let metadata = fs::metadata(tree.path("log")).unwrap();
+        assert!(!super::is_settled(&metadata, now));
+    }
+
+    #[test]
+    fn mmap_matches_reader() {
+        let mapped = Count::from_mmap("src/counter/count.rs").unwrap();
+        let read = Count::from_file("src/counter/count.rs").unwrap();
+        assert_eq!(mapped.newlines, read.newlines);
+        assert_eq!(mapped.words, read.words);
+        assert_eq!(mapped.bytes, read.bytes);
+        assert_eq!(mapped.chars, read.chars);
+        assert_eq!(mapped.max_line, read.max_line);
+
+        let mapped = Count::lines_from_mmap_chunked("src/counter/count.rs", &Budget::new(2)).unwrap();
+        assert_eq!(mapped, Count::lines_from_file("src/counter/count.rs").unwrap());
+    }
+
+    #[test]
+    fn reader() {
+        use std::fs;
+        use std::io::Cursor;
+
+        let expected = Count::from_file("README.md").unwrap();
+        let bytes = fs::read("README.md").unwrap();
+        assert_eq!(Count::from_reader(Cursor::new(&bytes)).unwrap(), expected);
+        assert_eq!(Count::from_buf_read(&bytes[..]).unwrap(), expected);
+        for capacity in &[1, 3, 7, 4096] {
+            assert_eq!(Count::from_reader_with_capacity(*capacity, &bytes[..]).unwrap(), expected,
+                       "capacity {}", capacity);
+        }
+    }
+
+    /// Interrupted reads are retried, other errors are returned.
+    #[test]
+    fn reader_errors() {
+        struct Flaky {
+            reads: usize,
+        }
+
+        impl io::Read for Flaky {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.reads += 1;
+                match self.reads {
+                    1 | 3 => Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted")),
+                    2 => { buf[..6].copy_from_slice(b"a b c\n"); Ok(6) }
+                    4 => Err(io::Error::other("broken")),
+                    _ => Ok(0),
+                }
+            }
+        }
+
+        let error = Count::from_reader(Flaky { reads: 0 }).unwrap_err();
+        assert_eq!(error.to_string(), "broken");
+        assert_eq!(error.path(), "");
+        let partial = error.partial().expect("partial counts");
+        assert_eq!((partial.newlines, partial.words, partial.bytes), (1, 3, 6));
+
+        let count = Count::from_reader(Flaky { reads: 4 }).unwrap();
+        assert_eq!(count, Count::new());
+    }
+
+    /// Errors from the mmap path should be the same as errors from the reader path.
+    #[test]
+    fn mmap_errors() {
+        for file in &["src", "does-not-exist"] {
+            let mapped = Count::from_mmap(file).err().unwrap();
+            let read = Count::from_file(file).err().unwrap();
+            assert_eq!(mapped.to_string(), read.to_string());
+            assert_eq!(mapped.path(), *file);
+        }
+    }
+
+    #[test]
+    fn error_kinds() {
+        use counter::Error;
+
+        match Count::from_file("does-not-exist") {
+            Err(Error::NotFound { ref path }) if path == "does-not-exist" => {} // do nothing, this is expected
+            result => panic!("unexpected result {:?}", result),
+        }
+        match Count::from_file("src") {
+            Err(Error::IsADirectory { ref path }) if path == "src" => {} // do nothing, this is expected
+            result => panic!("unexpected result {:?}", result),
+        }
+        match Count::lines_from_file("src") {
+            Err(Error::IsADirectory { .. }) => {} // do nothing, this is expected
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    /// Byte and line counts for regular files, pipes, and pseudo files all come from the data.
+    #[test]
+    fn sizes() {
+        use std::fs;
+        use test_util::Tree;
+
+        let tree = Tree::new("count-sizes", &[]);
+        fs::create_dir_all(&tree.root).unwrap();
+        for size in &[0, 1, 511, 512, 513, 4096, 4097, 10000, 100000] {
+            let path = tree.path("file");
+            fs::write(&path, "a\n".repeat(*size / 2) + &"b".repeat(*size % 2)).unwrap();
+            assert_eq!(Count::bytes_from_file(&path).unwrap().bytes, *size as u64, "size {}", size);
+            let count = Count::lines_from_file(&path).unwrap();
+            assert_eq!((count.newlines, count.bytes), (*size as u64 / 2, *size as u64), "size {}", size);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fifo() {
+        use std::fs;
+        use std::process::Command;
+        use std::thread;
+        use test_util::Tree;
+        use super::Error;
+
+        let tree = Tree::new("count-fifo", &[]);
+        fs::create_dir_all(&tree.root).unwrap();
+        let path = tree.path("fifo");
+        assert!(Command::new("mkfifo").arg(&path).status().unwrap().success());
+
+        // the functions that map files have to read a FIFO from the handle they opened, since
+        // opening it again would wait for another writer
+        type FromFile = fn(&str) -> Result<Count, Error>;
+        let from_files: [FromFile; 5] = [
+            |path| Count::bytes_from_file(path),
+            |path| Count::lines_from_file(path),
+            |path| Count::from_mmap(path),
+            |path| Count::lines_from_mmap_chunked(path, &Budget::new(2)),
+            |path| Metrics::new().count_file(path, &Budget::new(2)).map(|metrics| metrics.count()),
+        ];
+        for from_file in &from_files {
+            let writer = {
+                let path = path.clone();
+                thread::spawn(move || fs::write(path, "one\ntwo\nthree\n").unwrap())
+            };
+            let count = from_file(&path).unwrap();
+            writer.join().unwrap();
+            assert_eq!(count.bytes, 14);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn proc_files() {
+        use std::fs;
+
+        // the values change as the process runs, but the number of lines doesn't
+        let lines = fs::read_to_string("/proc/self/status").unwrap().lines().count() as u64;
+        assert_eq!(fs::metadata("/proc/self/status").unwrap().len(), 0);
+        assert!(Count::bytes_from_file("/proc/self/status").unwrap().bytes > 0);
+        let count = Count::lines_from_file("/proc/self/status").unwrap();
+        assert_eq!(count.newlines, lines);
+        assert!(count.bytes > 0);
+    }
+
+    #[test]
+    fn decompressed() {
+        use counter::Compression;
+        use counter::decompress::tests::compress;
+        use std::fs;
+        use test_util::Tree;
+
+        let text = "some words\nand more words\n".repeat(100);
+        let tree = Tree::new("count-decompressed", &["plain.txt"]);
+        fs::write(tree.path("plain.txt"), &text).unwrap();
+        let expected = Count::from_file(tree.path("plain.txt")).unwrap();
+        assert_eq!(Count::from_file_decompressed(tree.path("plain.txt")).unwrap(), expected);
+
+        for compression in &[Compression::Gzip, Compression::Bzip2, Compression::Xz, Compression::Zstd] {
+            let path = tree.path("compressed");
+            fs::write(&path, compress(*compression, text.as_bytes())).unwrap();
+            assert_eq!(Count::from_file_decompressed(&path).unwrap(), expected, "{:?}", compression);
+        }
+    }
 }
@@ -0,0 +1,126 @@
+extern crate bzip2;
+extern crate flate2;
+extern crate xz2;
+extern crate zstd;
+
+use std::io;
+use std::io::prelude::*;
+
+/// A compression format that `--decompress` understands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression format from the first bytes of a stream. At least six bytes are
+    /// needed to recognize every format.
+    pub fn detect(magic: &[u8]) -> Option<Compression> {
+        if magic.starts_with(&[0x1f, 0x8b]) { Some(Compression::Gzip) }
+        else if magic.starts_with(b"BZh") { Some(Compression::Bzip2) }
+        else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) { Some(Compression::Xz) }
+        else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) { Some(Compression::Zstd) }
+        else { None }
+    }
+}
+
+/// Return a reader for the decompressed contents of the given stream, or the stream itself if it
+/// isn't compressed. Streams with several concatenated members (like the output of `cat a.gz
+/// b.gz`) are decompressed completely.
+pub fn decompress<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let compression = loop {
+        match reader.fill_buf() {
+            Ok(magic) => break Compression::detect(magic),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    };
+
+    Ok(match compression {
+        None => Box::new(reader),
+        Some(Compression::Gzip) => Box::new(io::BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        Some(Compression::Bzip2) => Box::new(io::BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+        Some(Compression::Xz) => Box::new(io::BufReader::new(xz2::bufread::XzDecoder::new_multi_decoder(reader))),
+        Some(Compression::Zstd) => Box::new(io::BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{bzip2, flate2, xz2, zstd};
+    use super::{decompress, Compression};
+    use std::io::prelude::*;
+
+    /// Compress the given bytes.
+    pub fn compress(compression: Compression, bytes: &[u8]) -> Vec<u8> {
+        match compression {
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Bzip2 => {
+                let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Zstd => zstd::encode_all(bytes, 0).unwrap(),
+        }
+    }
+
+    const ALL: &[Compression] = &[Compression::Gzip, Compression::Bzip2, Compression::Xz, Compression::Zstd];
+
+    #[test]
+    fn detect() {
+        for compression in ALL {
+            let compressed = compress(*compression, b"some text\n");
+            assert_eq!(Compression::detect(&compressed), Some(*compression));
+        }
+        assert_eq!(Compression::detect(b"plain text"), None);
+        assert_eq!(Compression::detect(b""), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = "one two three\n".repeat(1000);
+        for compression in ALL {
+            let compressed = compress(*compression, text.as_bytes());
+            let mut decompressed = String::new();
+            decompress(&compressed[..]).unwrap().read_to_string(&mut decompressed).unwrap();
+            assert_eq!(decompressed, text, "{:?}", compression);
+        }
+
+        // anything else is passed through
+        let mut plain = String::new();
+        decompress(&b"plain text"[..]).unwrap().read_to_string(&mut plain).unwrap();
+        assert_eq!(plain, "plain text");
+    }
+
+    #[test]
+    fn concatenated() {
+        for compression in ALL {
+            let mut compressed = compress(*compression, b"first\n");
+            compressed.extend(compress(*compression, b"second\n"));
+            let mut decompressed = String::new();
+            decompress(&compressed[..]).unwrap().read_to_string(&mut decompressed).unwrap();
+            assert_eq!(decompressed, "first\nsecond\n", "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut compressed = compress(Compression::Gzip, &b"some text\n".repeat(100));
+        let len = compressed.len();
+        compressed.truncate(len / 2);
+        let mut decompressed = Vec::new();
+        assert!(decompress(&compressed[..]).unwrap().read_to_end(&mut decompressed).is_err());
+    }
+}
@@ -1,39 +1,48 @@
 use std::fmt;
-use counter::Count;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use counter::{is_archive, Count, Metrics};
 use options::Options;
 
+/// Displays the values in a set of metrics as a row of right aligned fields, like wc.
 pub struct Display<'a> {
-    count: &'a Count,
+    values: Vec<String>,
     opts: &'a Options,
+    width: Option<usize>,
 }
 
 impl<'a> Display<'a> {
-    pub fn new(count: &'a Count, opts: &'a Options) -> Self {
+    pub fn new(metrics: &Metrics, opts: &'a Options) -> Self {
         Display {
-            count: count,
-            opts: opts,
+            values: metrics.iter().map(|value| value.display()).collect(),
+            opts,
+            width: None,
         }
     }
 
-    fn field_width(&self) -> usize {
-        use std::cmp::min;
-        use std::cmp::max;
+    /// Use the given field width, usually from `plan_width()`, instead of picking one based on
+    /// the count being displayed.
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
 
-        fn digit_count(num: u64) -> usize {
-            if num > 0 { ((num as f64).log10() + 1f64) as usize }
-            else { 1 }
+    fn field_width(&self) -> usize {
+        if let Some(width) = self.width {
+            return width;
         }
 
+        use std::cmp::min;
+
         // for a single file scale the width
         if self.opts.files.len() <= 1 {
             // calculate the width of the longest field and scale the field with based on that. Use a
             // max field size of 7
-            let mut digits = 0;
-            if self.opts.lines { digits = max(digits, digit_count(self.count.newlines)); }
-            if self.opts.words { digits = max(digits, digit_count(self.count.words)); }
-            if self.opts.chars { digits = max(digits, digit_count(self.count.chars)); }
-            if self.opts.bytes { digits = max(digits, digit_count(self.count.bytes)); }
-            if self.opts.max_line { digits = max(digits, digit_count(self.count.max_line)); }
+            let digits = self.values.iter()
+                .map(|value| value.len())
+                .max()
+                .unwrap_or(0);
             min(digits, 7)
         }
         // for multiple files width is always 7
@@ -43,24 +52,325 @@ impl<'a> Display<'a> {
     }
 }
 
+/// Return the field width to use for every row when displaying the counts for the given files, the
+/// same way GNU wc picks it.
+///
+/// The width is the number of digits in the total size of all of the regular files, which is an
+/// upper bound for every count. Files that can't be stat-ed are ignored. If any of the inputs
+/// isn't a regular file (stdin from a pipe, a directory, a device) its size is unknown, so the
+/// width is at least 7. The same goes for every input with `--decompress`, and for archives with
+/// `--archive`. With a single input and a single count, the width is 1, and so it is for files
+/// from a `--files0-from` list that is streamed, since GNU wc doesn't know about those up front.
+pub fn plan_width(files: &[PathBuf], opts: &Options) -> usize {
+    if opts.files0_streamed {
+        return 1;
+    }
+    if files.len() <= 1 && Metrics::standard(opts).len() == 1 {
+        return 1;
+    }
+
+    let mut minimum_width = 1;
+    let mut regular_total = 0;
+    let stdin = [PathBuf::from("-")];
+    let files = if files.is_empty() { &stdin[..] } else { files };
+    for file in files {
+        match metadata(file) {
+            // the size of a compressed file or an archive says nothing about the size of its contents
+            Ok(ref metadata) if metadata.is_file() && !opts.decompress && !(opts.archive && is_archive(file)) => {
+                regular_total += metadata.len();
+            }
+            Ok(_) => minimum_width = 7,
+            Err(_) => {}
+        }
+    }
+
+    let mut width = 1;
+    while regular_total >= 10 {
+        regular_total /= 10;
+        width += 1;
+    }
+    ::std::cmp::max(width, minimum_width)
+}
+
+/// Return the metadata for the given file, or for stdin if the file is `-`.
+pub(crate) fn metadata(file: &Path) -> io::Result<fs::Metadata> {
+    if file == Path::new("-") { stdin_metadata() }
+    else { fs::metadata(file) }
+}
+
+#[cfg(unix)]
+fn stdin_metadata() -> io::Result<fs::Metadata> {
+    use std::os::unix::io::AsFd;
+    let stdin = io::stdin();
+    let fd = stdin.as_fd().try_clone_to_owned()?;
+    fs::File::from(fd).metadata()
+}
+
+#[cfg(not(unix))]
+fn stdin_metadata() -> io::Result<fs::Metadata> {
+    Err(io::Error::new(io::ErrorKind::Other, "can't stat stdin"))
+}
+
 impl<'a> fmt::Display for Display<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let width = self.field_width();
         let mut padding = "";
-        if self.opts.lines { try!(write!(f, "{}{: >width$}", padding, self.count.newlines, width = width)); padding = " "; }
-        if self.opts.words { try!(write!(f, "{}{: >width$}", padding, self.count.words, width = width)); padding = " "; }
-        if self.opts.chars { try!(write!(f, "{}{: >width$}", padding, self.count.chars, width = width)); padding = " "; }
-        if self.opts.bytes { try!(write!(f, "{}{: >width$}", padding, self.count.bytes, width = width)); padding = " "; }
-        if self.opts.max_line { try!(write!(f, "{}{: >width$}", padding, self.count.max_line, width = width)); }
+        for value in &self.values {
+            write!(f, "{}{: >width$}", padding, value, width = width)?;
+            padding = " ";
+        }
+        Ok(())
+    }
+}
+
+/// Displays the counts for an input (or the total) as a JSON object, with a member for each of the
+/// enabled counts, or for each value in a set of metrics.
+pub struct Json<'a> {
+    record_type: Option<&'a str>,
+    file: Option<&'a str>,
+    group: Option<&'a str>,
+    archive: Option<&'a str>,
+    values: Option<Vec<(String, u64)>>,
+    error: Option<&'a dyn fmt::Display>,
+    opts: &'a Options,
+}
+
+impl<'a> Json<'a> {
+    pub fn new(opts: &'a Options) -> Self {
+        Json {
+            record_type: None,
+            file: None,
+            group: None,
+            archive: None,
+            values: None,
+            error: None,
+            opts,
+        }
+    }
+
+    /// Include a `type` member, to tell records apart when they are streamed one per line.
+    pub fn record_type(mut self, record_type: &'a str) -> Self {
+        self.record_type = Some(record_type);
+        self
+    }
+
+    /// Include a `file` member with the given name.
+    pub fn file(mut self, file: &'a str) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    /// Include a `group` member with the given key.
+    pub fn group(mut self, group: &'a str) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    /// Include an `archive` member with the given name, for the subtotal of the files inside of it.
+    pub fn archive(mut self, archive: &'a str) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Include members for the enabled counts.
+    pub fn count(self, count: &Count) -> Self {
+        let metrics = Metrics::from_count(count, self.opts);
+        self.metrics(&metrics)
+    }
+
+    /// Include a member for each value in the set of metrics.
+    pub fn metrics(mut self, metrics: &Metrics) -> Self {
+        self.values = Some(metrics.iter().map(|value| (value.name().to_owned(), value.value())).collect());
+        self
+    }
+
+    /// Include an `error` member with the given message.
+    pub fn error(mut self, error: &'a dyn fmt::Display) -> Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+impl<'a> fmt::Display for Json<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut separator = "";
+        write!(f, "{{")?;
+        if let Some(record_type) = self.record_type {
+            write!(f, "\"type\":{}", JsonString(record_type))?;
+            separator = ",";
+        }
+        if let Some(file) = self.file {
+            write!(f, "{}\"file\":{}", separator, JsonString(file))?;
+            separator = ",";
+        }
+        if let Some(group) = self.group {
+            write!(f, "{}\"group\":{}", separator, JsonString(group))?;
+            separator = ",";
+        }
+        if let Some(archive) = self.archive {
+            write!(f, "{}\"archive\":{}", separator, JsonString(archive))?;
+            separator = ",";
+        }
+        if let Some(ref values) = self.values {
+            for &(ref name, value) in values {
+                write!(f, "{}{}:{}", separator, JsonString(name), value)?;
+                separator = ",";
+            }
+        }
+        if let Some(error) = self.error {
+            write!(f, "{}\"error\":{}", separator, JsonString(&error.to_string()))?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Displays a string as a quoted and escaped JSON string.
+pub struct JsonString<'a>(pub &'a str);
+
+impl<'a> fmt::Display for JsonString<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+/// The separator used between the values in a `Delimited` row.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Separator {
+    /// Comma separated values, quoted as described in RFC 4180.
+    Comma,
+    /// Tab separated values, with tabs, newlines, and backslashes escaped with a backslash.
+    Tab,
+}
+
+impl Separator {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Separator::Comma => ",",
+            Separator::Tab => "\t",
+        }
+    }
+}
+
+/// Displays the counts for an input (or the total) as a row of delimiter separated values. The
+/// columns are the enabled counts (or the values in a set of metrics), in the same order as
+/// `Display`, followed by the file name and an error message.
+pub struct Delimited<'a> {
+    separator: Separator,
+    file: &'a str,
+    /// The values for the count columns, which are left empty if there was an error.
+    values: Vec<String>,
+    error: Option<&'a dyn fmt::Display>,
+    opts: &'a Options,
+}
+
+impl<'a> Delimited<'a> {
+    pub fn new(separator: Separator, file: &'a str, opts: &'a Options) -> Self {
+        Delimited {
+            separator,
+            file,
+            values: vec![String::new(); Metrics::standard(opts).len()],
+            error: None,
+            opts,
+        }
+    }
+
+    /// Return the header row naming each column, with a count column for each value in the set
+    /// of metrics.
+    pub fn header(separator: Separator, metrics: &Metrics, opts: &Options) -> String {
+        let mut columns = metrics.names();
+        columns.push(if opts.group_by.is_some() { "group" } else { "file" }.to_owned());
+        columns.push("error".to_owned());
+        columns.join(separator.as_str())
+    }
+
+    /// Fill in the count columns.
+    pub fn count(self, count: &Count) -> Self {
+        let metrics = Metrics::from_count(count, self.opts);
+        self.metrics(&metrics)
+    }
+
+    /// Fill in a count column for each value in the set of metrics. The columns are still left
+    /// empty if there is an error, so this also sets how many there are for error rows.
+    pub fn metrics(mut self, metrics: &Metrics) -> Self {
+        self.values = metrics.iter().map(|value| value.display()).collect();
+        self
+    }
+
+    /// Fill in the error column.
+    pub fn error(mut self, error: &'a dyn fmt::Display) -> Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+impl<'a> fmt::Display for Delimited<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = self.separator.as_str();
+
+        // counts are left empty if there was an error
+        for value in &self.values {
+            if self.error.is_none() { write!(f, "{}", value)?; }
+            write!(f, "{}", separator)?;
+        }
+
+        write!(f, "{}{}", Field(self.file, self.separator), separator)?;
+        if let Some(error) = self.error {
+            write!(f, "{}", Field(&error.to_string(), self.separator))?;
+        }
         Ok(())
     }
 }
 
+/// Displays a single value, quoted or escaped as needed for the separator.
+struct Field<'a>(&'a str, Separator);
+
+impl<'a> fmt::Display for Field<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.1 {
+            Separator::Comma => {
+                if self.0.contains([',', '"', '\n', '\r']) {
+                    write!(f, "\"{}\"", self.0.replace('"', "\"\""))
+                }
+                else {
+                    write!(f, "{}", self.0)
+                }
+            }
+            Separator::Tab => {
+                for c in self.0.chars() {
+                    match c {
+                        '\t' => write!(f, "\\t")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\\' => write!(f, "\\\\")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use options::Options;
-    use counter::Count;
+    use counter::metric::tests::BlankLines;
+    use counter::{Count, Metrics};
+    use super::{Json, JsonString, Delimited, Separator, plan_width};
     use std::fmt::Write;
+    use std::path::PathBuf;
 
     #[test]
     fn everything() {
@@ -143,4 +453,173 @@ mod tests {
         write!(s, "{}", count.display(&opts)).unwrap();
         assert_eq!(s, "      0       1       2       3       4");
     }
+
+    #[test]
+    fn fixed_width() {
+        let mut s = String::new();
+        let mut count = Count::new();
+        count.newlines = 1;
+        count.words = 22;
+        count.bytes = 333;
+
+        let opts = Options::test_empty().unwrap();
+        write!(s, "{}", count.display(&opts).width(4)).unwrap();
+        assert_eq!(s, "   1   22  333");
+    }
+
+    #[test]
+    fn planned_width() {
+        let opts = Options::test_empty().unwrap();
+        let size = ::std::fs::metadata("Cargo.toml").unwrap().len();
+        let files = vec![PathBuf::from("Cargo.toml")];
+        assert_eq!(plan_width(&files, &opts), size.to_string().len());
+
+        let total = size * 2;
+        let files = vec![PathBuf::from("Cargo.toml"), PathBuf::from("Cargo.toml"), PathBuf::from("missing")];
+        assert_eq!(plan_width(&files, &opts), total.to_string().len());
+
+        // the size of a directory isn't known
+        let files = vec![PathBuf::from("Cargo.toml"), PathBuf::from("src")];
+        assert_eq!(plan_width(&files, &opts), 7);
+    }
+
+    /// GNU wc reads the names from a pipe one at a time, so it uses a width of 1 for them.
+    #[test]
+    fn planned_width_streamed() {
+        let mut opts = Options::test_args(vec!["Cargo.toml", "src"]).unwrap();
+        assert_eq!(plan_width(&opts.files, &opts), 7);
+        opts.files0_streamed = true;
+        assert_eq!(plan_width(&opts.files, &opts), 1);
+    }
+
+    #[test]
+    fn planned_width_single_count() {
+        let opts = Options::test_args(vec!["-l"]).unwrap();
+        assert_eq!(plan_width(&[PathBuf::from("src")], &opts), 1);
+        assert_eq!(plan_width(&[], &opts), 1);
+        assert_eq!(plan_width(&[PathBuf::from("src"), PathBuf::from("src")], &opts), 7);
+    }
+
+    #[test]
+    fn json() {
+        let mut count = Count::new();
+        count.newlines = 0;
+        count.words = 1;
+        count.chars = 2;
+        count.bytes = 3;
+        count.max_line = 4;
+
+        let mut opts = Options::test_empty().unwrap();
+        assert_eq!(Json::new(&opts).file("f").count(&count).to_string(),
+                   r#"{"file":"f","lines":0,"words":1,"bytes":3}"#);
+
+        opts.chars = true;
+        opts.max_line = true;
+        assert_eq!(count.json(&opts).to_string(),
+                   r#"{"lines":0,"words":1,"chars":2,"bytes":3,"max_line_length":4}"#);
+
+        opts.lines = false;
+        opts.words = false;
+        opts.chars = false;
+        opts.max_line = false;
+        assert_eq!(count.json(&opts).file("f").to_string(), r#"{"file":"f","bytes":3}"#);
+    }
+
+    #[test]
+    fn json_record_type() {
+        let opts = Options::test_empty().unwrap();
+        let count = Count::new();
+        assert_eq!(count.json(&opts).record_type("file").file("f").to_string(),
+                   r#"{"type":"file","file":"f","lines":0,"words":0,"bytes":0}"#);
+        assert_eq!(count.json(&opts).record_type("total").to_string(),
+                   r#"{"type":"total","lines":0,"words":0,"bytes":0}"#);
+        assert_eq!(count.json(&opts).record_type("group").group("rs").to_string(),
+                   r#"{"type":"group","group":"rs","lines":0,"words":0,"bytes":0}"#);
+        assert_eq!(count.json(&opts).record_type("subtotal").archive("t.tar").to_string(),
+                   r#"{"type":"subtotal","archive":"t.tar","lines":0,"words":0,"bytes":0}"#);
+    }
+
+    #[test]
+    fn json_error() {
+        let opts = Options::test_empty().unwrap();
+        let error = "No such file or directory";
+        assert_eq!(Json::new(&opts).file("missing").error(&error).to_string(),
+                   r#"{"file":"missing","error":"No such file or directory"}"#);
+    }
+
+    #[test]
+    fn json_string() {
+        let mut s = String::new();
+        write!(s, "{}", JsonString("a \"quoted\" \\ file\nname\t\u{1}💖")).unwrap();
+        assert_eq!(s, r#""a \"quoted\" \\ file\nname\t\u0001💖""#);
+    }
+
+    #[test]
+    fn delimited_header() {
+        let mut opts = Options::test_empty().unwrap();
+        assert_eq!(Delimited::header(Separator::Comma, &Metrics::standard(&opts), &opts), "lines,words,bytes,file,error");
+
+        opts.chars = true;
+        opts.max_line = true;
+        assert_eq!(Delimited::header(Separator::Tab, &Metrics::standard(&opts), &opts),
+                   "lines\twords\tchars\tbytes\tmax_line_length\tfile\terror");
+
+        let opts = Options::test_args(vec!["--group-by=ext"]).unwrap();
+        assert_eq!(Delimited::header(Separator::Comma, &Metrics::standard(&opts), &opts), "lines,words,bytes,group,error");
+    }
+
+    #[test]
+    fn delimited() {
+        let mut count = Count::new();
+        count.newlines = 0;
+        count.words = 1;
+        count.chars = 2;
+        count.bytes = 3;
+        count.max_line = 4;
+
+        let opts = Options::test_empty().unwrap();
+        let row = Delimited::new(Separator::Comma, "file", &opts).count(&count);
+        assert_eq!(row.to_string(), "0,1,3,file,");
+        let row = Delimited::new(Separator::Tab, "file", &opts).count(&count);
+        assert_eq!(row.to_string(), "0\t1\t3\tfile\t");
+    }
+
+    #[test]
+    fn delimited_error() {
+        let opts = Options::test_empty().unwrap();
+        let error = "Is a directory";
+        let row = Delimited::new(Separator::Comma, "dir", &opts).error(&error);
+        assert_eq!(row.to_string(), ",,,dir,Is a directory");
+    }
+
+    /// Registered metrics get a column after the standard counts, in every format.
+    #[test]
+    fn metrics() {
+        let opts = Options::test_args(vec!["-l"]).unwrap();
+        let mut metrics = Metrics::standard(&opts);
+        metrics.register(Box::new(BlankLines::default()));
+        metrics.update(b"one\n\ntwo\n\n");
+
+        assert_eq!(metrics.display(&opts).to_string(), "4 2");
+        assert_eq!(metrics.json(&opts).file("f").to_string(), r#"{"file":"f","lines":4,"blank_lines":2}"#);
+        assert_eq!(Delimited::header(Separator::Comma, &metrics, &opts), "lines,blank_lines,file,error");
+        let row = Delimited::new(Separator::Comma, "f", &opts).metrics(&metrics);
+        assert_eq!(row.to_string(), "4,2,f,");
+        let error = "Is a directory";
+        let row = Delimited::new(Separator::Comma, "dir", &opts).metrics(&metrics).error(&error);
+        assert_eq!(row.to_string(), ",,dir,Is a directory");
+    }
+
+    #[test]
+    fn delimited_quoting() {
+        let opts = Options::test_empty().unwrap();
+        let count = Count::new();
+
+        let row = Delimited::new(Separator::Comma, "a \"b\", c", &opts).count(&count);
+        assert_eq!(row.to_string(), "0,0,0,\"a \"\"b\"\", c\",");
+        let row = Delimited::new(Separator::Comma, "a\nb", &opts).count(&count);
+        assert_eq!(row.to_string(), "0,0,0,\"a\nb\",");
+        let row = Delimited::new(Separator::Tab, "a\tb\nc\\d", &opts).count(&count);
+        assert_eq!(row.to_string(), "0\t0\t0\ta\\tb\\nc\\\\d\t");
+    }
 }
@@ -0,0 +1,191 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use super::Count;
+
+/// A problem counting an input.
+///
+/// Errors from readers that aren't files (see `Count::from_reader()`) have an empty path, and
+/// errors for stdin use `-`.
+#[derive(Debug)]
+pub enum Error {
+    /// The file doesn't exist.
+    NotFound { path: PathBuf },
+    /// The file is a directory.
+    IsADirectory { path: PathBuf },
+    /// The file can't be opened or read because of its permissions.
+    PermissionDenied { path: PathBuf },
+    /// Anything else, like a read failing partway through the input. `partial` has the counts
+    /// for everything before the failure, or is `None` if the input couldn't be opened.
+    Io {
+        path: PathBuf,
+        error: io::Error,
+        partial: Option<Count>,
+    },
+}
+
+impl Error {
+    /// Return the error for a read that failed, with the counts for what was read before it
+    /// happened. Reading a directory is the only read failure with its own kind, since the input
+    /// was opened.
+    ///
+    /// Errors from opening an input are converted with `From` instead.
+    pub fn from_io(error: io::Error, partial: Count) -> Self {
+        let path = PathBuf::new();
+        match error.kind() {
+            io::ErrorKind::IsADirectory => Error::IsADirectory { path },
+            _ => Error::Io { path, error, partial: Some(partial) },
+        }
+    }
+
+    /// Return this error for the given path.
+    pub fn with_path<P: AsRef<Path>>(mut self, file: P) -> Self {
+        match self {
+            Error::NotFound { ref mut path } |
+            Error::IsADirectory { ref mut path } |
+            Error::PermissionDenied { ref mut path } |
+            Error::Io { ref mut path, .. } => *path = file.as_ref().to_owned(),
+        }
+        self
+    }
+
+    /// The path of the input that couldn't be counted.
+    pub fn path(&self) -> &Path {
+        match *self {
+            Error::NotFound { ref path } |
+            Error::IsADirectory { ref path } |
+            Error::PermissionDenied { ref path } |
+            Error::Io { ref path, .. } => path,
+        }
+    }
+
+    /// The counts for the part of the input that was read before the error, if the input was
+    /// opened. These are zeros if the first read failed.
+    pub fn partial(&self) -> Option<&Count> {
+        match *self {
+            Error::Io { partial: Some(ref partial), .. } => Some(partial),
+            _ => None,
+        }
+    }
+
+    /// The counts to print for the input, like GNU wc: zeros for a directory, and whatever was
+    /// read before a read failed, even if that was nothing. There are none for inputs that
+    /// couldn't be opened.
+    pub fn counts(&self) -> Option<Count> {
+        match *self {
+            Error::IsADirectory { .. } => Some(Count::new()),
+            _ => self.partial().cloned(),
+        }
+    }
+}
+
+/// Run `f`, setting the path of any error it returns to `file`.
+pub(super) fn with_path<T, F>(file: &Path, f: F) -> Result<T, Error>
+    where F: FnOnce() -> Result<T, Error>
+{
+    f().map_err(|e| e.with_path(file))
+}
+
+/// Errors converted with `?` are failures to open the input, so they have no counts.
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        let path = PathBuf::new();
+        match error.kind() {
+            io::ErrorKind::NotFound => Error::NotFound { path },
+            io::ErrorKind::IsADirectory => Error::IsADirectory { path },
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied { path },
+            _ => Error::Io { path, error, partial: None },
+        }
+    }
+}
+
+/// The message doesn't include the path, so it can be put next to the path however the output
+/// format needs.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NotFound { .. } => write!(f, "No such file or directory"),
+            Error::IsADirectory { .. } => write!(f, "Is a directory"),
+            Error::PermissionDenied { .. } => write!(f, "Permission denied"),
+            Error::Io { ref error, .. } => write!(f, "{}", message(error)),
+        }
+    }
+}
+
+/// Return the message for an I/O error without the "(os error N)" that Rust adds, so it reads
+/// like the `strerror()` messages GNU wc prints.
+fn message(error: &io::Error) -> String {
+    let message = error.to_string();
+    match error.raw_os_error() {
+        Some(code) => message.trim_end_matches(&format!(" (os error {})", code)).to_owned(),
+        None => message,
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use counter::Count;
+    use std::io;
+    use std::path::Path;
+
+    #[test]
+    fn kinds() {
+        let error = Error::from(io::Error::from(io::ErrorKind::NotFound)).with_path("missing");
+        match error {
+            Error::NotFound { ref path } if path == Path::new("missing") => {} // do nothing, this is expected
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(error.to_string(), "No such file or directory");
+        assert_eq!(error.path(), Path::new("missing"));
+        assert_eq!(error.counts(), None);
+
+        let error = Error::from(io::Error::from(io::ErrorKind::IsADirectory));
+        assert_eq!(error.to_string(), "Is a directory");
+        assert_eq!(error.path(), Path::new(""));
+        assert_eq!(error.counts(), Some(Count::new()));
+
+        let error = Error::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(error.to_string(), "Permission denied");
+
+        // EIO
+        let error = Error::from(io::Error::from_raw_os_error(5));
+        assert_eq!(error.to_string(), "Input/output error");
+    }
+
+    #[test]
+    fn partial() {
+        let mut count = Count::new();
+        count.bytes = 10;
+        let error = Error::from_io(io::Error::other("broken"), count).with_path("file");
+        assert_eq!(error.to_string(), "broken");
+        assert_eq!(error.path(), Path::new("file"));
+        assert_eq!(error.partial(), Some(&count));
+        assert_eq!(error.counts(), Some(count));
+
+        // a failure on the first read still has a row of zeros
+        let error = Error::from_io(io::Error::from_raw_os_error(5), Count::new());
+        assert_eq!(error.partial(), Some(&Count::new()));
+        assert_eq!(error.counts(), Some(Count::new()));
+
+        // a read can be denied after the input was opened
+        let error = Error::from_io(io::Error::from(io::ErrorKind::PermissionDenied), Count::new());
+        assert_eq!(error.to_string(), "permission denied");
+        assert_eq!(error.counts(), Some(Count::new()));
+
+        // but an input that couldn't be opened has none
+        let error = Error::from(io::Error::other("broken"));
+        assert_eq!(error.partial(), None);
+        assert_eq!(error.counts(), None);
+    }
+}
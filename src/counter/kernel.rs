@@ -0,0 +1,417 @@
+//! Slice based counting kernel.
+//!
+//! Input is processed in blocks of 64 bytes. For each block we build three bit masks (one bit per
+//! byte): newlines, white space, and UTF-8 continuation bytes. All of the counts can then be
+//! computed from the masks with a few bit operations and `count_ones()`. Building the masks is
+//! done with AVX2 or SSE2 when the CPU supports it, with a plain scalar loop as the fallback.
+
+use std::cmp::max;
+use std::sync::OnceLock;
+use super::Count;
+
+/// The number of bytes processed at a time.
+const BLOCK: usize = 64;
+
+/// Counting state carried over from one slice to the next.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct State {
+    /// `true` if the last byte seen was part of a word.
+    pub in_word: bool,
+    /// The number of bytes seen since the last newline.
+    pub line_length: u64,
+}
+
+/// Bit masks for a block of up to 64 bytes. Bit `n` is for byte `n` of the block.
+struct Masks {
+    newline: u64,
+    space: u64,
+    continuation: u64,
+}
+
+/// The instruction sets the kernel can use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Isa {
+    Avx2,
+    Sse2,
+    Scalar,
+}
+
+/// Return the best instruction set the CPU supports. It is only detected once.
+fn isa() -> Isa {
+    static ISA: OnceLock<Isa> = OnceLock::new();
+    *ISA.get_or_init(|| {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return Isa::Avx2;
+            }
+            if is_x86_feature_detected!("sse2") {
+                return Isa::Sse2;
+            }
+        }
+        Isa::Scalar
+    })
+}
+
+/// Return `true` if the given byte is white space.
+///
+/// Bytes are treated as if they were Latin-1 characters, so this is the same as
+/// `(byte as char).is_whitespace()`.
+#[inline(always)]
+pub fn is_space(byte: u8) -> bool {
+    matches!(byte, b'\t' | b'\n' | 0x0b | 0x0c | b'\r' | b' ' | 0x85 | 0xa0)
+}
+
+/// Return `true` if the given byte is a UTF-8 continuation byte.
+#[inline(always)]
+pub fn is_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
+
+/// Add the newline, word, character, byte, and maximum line length counts for `bytes` to
+/// `count`, continuing from (and updating) `state`.
+pub fn count(bytes: &[u8], count: &mut Count, state: &mut State) {
+    match isa() {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Isa::Avx2 => unsafe { x86::count_avx2(bytes, count, state) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Isa::Sse2 => unsafe { x86::count_sse2(bytes, count, state) },
+        _ => count_with(bytes, count, state, masks_scalar),
+    }
+}
+
+/// Return the number of newlines in `bytes`.
+pub fn newlines(bytes: &[u8]) -> u64 {
+    match isa() {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Isa::Avx2 => unsafe { x86::newlines_avx2(bytes) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Isa::Sse2 => unsafe { x86::newlines_sse2(bytes) },
+        _ => newlines_scalar(bytes),
+    }
+}
+
+fn newlines_scalar(bytes: &[u8]) -> u64 {
+    bytes.iter().filter(|b| **b == b'\n').count() as u64
+}
+
+fn masks_scalar(block: &[u8]) -> Masks {
+    let mut masks = Masks { newline: 0, space: 0, continuation: 0 };
+    for (i, b) in block.iter().enumerate() {
+        let bit = 1 << i;
+        if *b == b'\n' { masks.newline |= bit; }
+        if is_space(*b) { masks.space |= bit; }
+        if is_continuation(*b) { masks.continuation |= bit; }
+    }
+    masks
+}
+
+/// Count `bytes` in full blocks using `masks` to build the bit masks for each block. The tail
+/// that doesn't fill a whole block is handled with the scalar mask builder.
+#[inline(always)]
+fn count_with<F>(bytes: &[u8], count: &mut Count, state: &mut State, masks: F)
+    where F: Fn(&[u8]) -> Masks
+{
+    let mut blocks = bytes.chunks_exact(BLOCK);
+    for block in &mut blocks {
+        count_block(&masks(block), BLOCK, count, state);
+    }
+
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        count_block(&masks_scalar(tail), tail.len(), count, state);
+    }
+}
+
+/// Update the counts for a block of `len` bytes using the block's bit masks.
+#[inline(always)]
+fn count_block(masks: &Masks, len: usize, count: &mut Count, state: &mut State) {
+    let valid = if len == BLOCK { !0 } else { (1 << len) - 1 };
+
+    count.bytes += len as u64;
+    count.newlines += u64::from(masks.newline.count_ones());
+
+    // every byte that isn't a continuation byte starts a character
+    count.chars += len as u64 - u64::from(masks.continuation.count_ones());
+
+    // a word starts at each word byte that is preceded by a white space byte
+    let word = valid & !masks.space;
+    let previous = (word << 1) | state.in_word as u64;
+    count.words += u64::from((word & !previous).count_ones());
+    state.in_word = word >> (len - 1) & 1 == 1;
+
+    // walk the newlines in the block to find line lengths
+    let mut newline = masks.newline;
+    let mut start = 0;
+    while newline != 0 {
+        let position = u64::from(newline.trailing_zeros());
+        state.line_length += position - start;
+        count.max_line = max(count.max_line, state.line_length);
+        state.line_length = 0;
+        start = position + 1;
+        newline &= newline - 1;
+    }
+    state.line_length += len as u64 - start;
+    count.max_line = max(count.max_line, state.line_length);
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    use super::{count_with, newlines_scalar, Masks, State, BLOCK};
+    use counter::Count;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn count_avx2(bytes: &[u8], count: &mut Count, state: &mut State) {
+        count_with(bytes, count, state, |block| masks_avx2(block))
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn count_sse2(bytes: &[u8], count: &mut Count, state: &mut State) {
+        count_with(bytes, count, state, |block| masks_sse2(block))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn newlines_avx2(bytes: &[u8]) -> u64 {
+        let newline = _mm256_set1_epi8(b'\n' as i8);
+        let mut blocks = bytes.chunks_exact(32);
+        let mut total = 0;
+        for block in &mut blocks {
+            let v = _mm256_loadu_si256(block.as_ptr() as *const __m256i);
+            let mask = _mm256_movemask_epi8(_mm256_cmpeq_epi8(v, newline)) as u32;
+            total += u64::from(mask.count_ones());
+        }
+        total + newlines_scalar(blocks.remainder())
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn newlines_sse2(bytes: &[u8]) -> u64 {
+        let newline = _mm_set1_epi8(b'\n' as i8);
+        let mut blocks = bytes.chunks_exact(16);
+        let mut total = 0;
+        for block in &mut blocks {
+            let v = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+            let mask = _mm_movemask_epi8(_mm_cmpeq_epi8(v, newline)) as u32;
+            total += u64::from(mask.count_ones());
+        }
+        total + newlines_scalar(blocks.remainder())
+    }
+
+    /// Build the masks for a full 64 byte block, 32 bytes at a time.
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn masks_avx2(block: &[u8]) -> Masks {
+        debug_assert_eq!(block.len(), BLOCK);
+
+        let newline = _mm256_set1_epi8(b'\n' as i8);
+        let tab = _mm256_set1_epi8(b'\t' as i8);
+        let four = _mm256_set1_epi8(4);
+        let space = _mm256_set1_epi8(b' ' as i8);
+        let nel = _mm256_set1_epi8(0x85u8 as i8);
+        let nbsp = _mm256_set1_epi8(0xa0u8 as i8);
+        let top_bits = _mm256_set1_epi8(0xc0u8 as i8);
+        let continuation = _mm256_set1_epi8(0x80u8 as i8);
+
+        let mut masks = Masks { newline: 0, space: 0, continuation: 0 };
+        for half in 0..2 {
+            let v = _mm256_loadu_si256(block.as_ptr().add(half * 32) as *const __m256i);
+
+            // bytes from \t to \r are all white space, check for them with a single unsigned
+            // range check: (v - \t) <= 4
+            let offset = _mm256_sub_epi8(v, tab);
+            let control = _mm256_cmpeq_epi8(_mm256_min_epu8(offset, four), offset);
+            let spaces = _mm256_or_si256(
+                _mm256_or_si256(control, _mm256_cmpeq_epi8(v, space)),
+                _mm256_or_si256(_mm256_cmpeq_epi8(v, nel), _mm256_cmpeq_epi8(v, nbsp)));
+
+            let continuations = _mm256_cmpeq_epi8(_mm256_and_si256(v, top_bits), continuation);
+
+            let shift = half * 32;
+            masks.newline |= u64::from(_mm256_movemask_epi8(_mm256_cmpeq_epi8(v, newline)) as u32) << shift;
+            masks.space |= u64::from(_mm256_movemask_epi8(spaces) as u32) << shift;
+            masks.continuation |= u64::from(_mm256_movemask_epi8(continuations) as u32) << shift;
+        }
+        masks
+    }
+
+    /// Build the masks for a full 64 byte block, 16 bytes at a time.
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn masks_sse2(block: &[u8]) -> Masks {
+        debug_assert_eq!(block.len(), BLOCK);
+
+        let newline = _mm_set1_epi8(b'\n' as i8);
+        let tab = _mm_set1_epi8(b'\t' as i8);
+        let four = _mm_set1_epi8(4);
+        let space = _mm_set1_epi8(b' ' as i8);
+        let nel = _mm_set1_epi8(0x85u8 as i8);
+        let nbsp = _mm_set1_epi8(0xa0u8 as i8);
+        let top_bits = _mm_set1_epi8(0xc0u8 as i8);
+        let continuation = _mm_set1_epi8(0x80u8 as i8);
+
+        let mut masks = Masks { newline: 0, space: 0, continuation: 0 };
+        for quarter in 0..4 {
+            let v = _mm_loadu_si128(block.as_ptr().add(quarter * 16) as *const __m128i);
+
+            // see masks_avx2() for the \t to \r range check
+            let offset = _mm_sub_epi8(v, tab);
+            let control = _mm_cmpeq_epi8(_mm_min_epu8(offset, four), offset);
+            let spaces = _mm_or_si128(
+                _mm_or_si128(control, _mm_cmpeq_epi8(v, space)),
+                _mm_or_si128(_mm_cmpeq_epi8(v, nel), _mm_cmpeq_epi8(v, nbsp)));
+
+            let continuations = _mm_cmpeq_epi8(_mm_and_si128(v, top_bits), continuation);
+
+            let shift = quarter * 16;
+            masks.newline |= u64::from(_mm_movemask_epi8(_mm_cmpeq_epi8(v, newline)) as u32) << shift;
+            masks.space |= u64::from(_mm_movemask_epi8(spaces) as u32) << shift;
+            masks.continuation |= u64::from(_mm_movemask_epi8(continuations) as u32) << shift;
+        }
+        masks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count, count_with, masks_scalar, newlines, newlines_scalar, is_space, State};
+    use counter::Count;
+
+    /// Count with the byte at a time algorithm the kernel replaced, for comparison.
+    fn reference(bytes: &[u8]) -> Count {
+        let mut count = Count::new();
+        let mut line_length = 0;
+        let mut in_word = false;
+        for b in bytes {
+            count.bytes += 1;
+            if *b == b'\n' {
+                count.newlines += 1;
+                line_length = 0;
+            }
+            else {
+                line_length += 1;
+                count.max_line = ::std::cmp::max(count.max_line, line_length);
+            }
+            if !in_word && !is_space(*b) { count.words += 1; }
+            in_word = !is_space(*b);
+            if b & 0b1100_0000 != 0b1000_0000 { count.chars += 1; }
+        }
+        count
+    }
+
+    /// Some input that exercises block boundaries, long lines, and multi-byte characters.
+    fn sample() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for i in 0..500 {
+            bytes.extend("word ".repeat(i % 37).bytes());
+            bytes.extend("இঈஇ 💖\t\r".bytes());
+            bytes.extend([0x85u8, 0xa0, 0x0b, 0x0c, 0x1c, 0xff].iter());
+            if i % 3 == 0 { bytes.push(b'\n'); }
+        }
+        bytes
+    }
+
+    fn assert_same(a: &Count, b: &Count) {
+        assert_eq!(a.newlines, b.newlines);
+        assert_eq!(a.words, b.words);
+        assert_eq!(a.bytes, b.bytes);
+        assert_eq!(a.chars, b.chars);
+        assert_eq!(a.max_line, b.max_line);
+    }
+
+    #[test]
+    fn space_matches_char() {
+        for b in 0..=255u8 {
+            assert_eq!(is_space(b), (b as char).is_whitespace(), "byte {:#x}", b);
+        }
+    }
+
+    #[test]
+    fn matches_reference() {
+        let bytes = sample();
+        for len in (0..200).chain(vec![bytes.len()]) {
+            let bytes = &bytes[..len];
+
+            let mut simd = Count::new();
+            count(bytes, &mut simd, &mut State::default());
+            assert_same(&simd, &reference(bytes));
+
+            let mut scalar = Count::new();
+            count_with(bytes, &mut scalar, &mut State::default(), masks_scalar);
+            assert_same(&scalar, &reference(bytes));
+        }
+    }
+
+    /// Counting in pieces should give the same result as counting all at once.
+    #[test]
+    fn split_slices() {
+        let bytes = sample();
+        for split in &[1, 7, 63, 64, 65, 1000] {
+            let mut c = Count::new();
+            let mut state = State::default();
+            for piece in bytes.chunks(*split) {
+                count(piece, &mut c, &mut state);
+            }
+            assert_same(&c, &reference(&bytes));
+        }
+    }
+
+    /// SSE2 is part of x86_64, so the scalar fallback should never be picked there.
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn isa() {
+        assert_ne!(super::isa(), super::Isa::Scalar);
+    }
+
+    /// The x86 kernels, called directly, so the ones `isa()` doesn't pick on this CPU are checked
+    /// too. SSE2 is always there on x86_64, AVX2 only if the CPU has it.
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn x86_kernels() {
+        use super::x86::{count_avx2, count_sse2, newlines_avx2, newlines_sse2};
+
+        type CountFn = unsafe fn(&[u8], &mut Count, &mut State);
+        type NewlinesFn = unsafe fn(&[u8]) -> u64;
+        let mut kernels: Vec<(&str, CountFn, NewlinesFn)> = vec![("sse2", count_sse2, newlines_sse2)];
+        if is_x86_feature_detected!("avx2") {
+            kernels.push(("avx2", count_avx2, newlines_avx2));
+        }
+
+        let bytes = sample();
+        for (name, count_fn, newlines_fn) in kernels {
+            // SAFETY: the target features of every kernel in the list are available
+            for len in (0..200).chain(vec![bytes.len()]) {
+                let bytes = &bytes[..len];
+                let mut c = Count::new();
+                unsafe { count_fn(bytes, &mut c, &mut State::default()) };
+                assert_eq!(c, reference(bytes), "{} with {} bytes", name, len);
+                assert_eq!(unsafe { newlines_fn(bytes) }, reference(bytes).newlines, "{} with {} bytes", name, len);
+            }
+
+            // pieces that end on, before, and after a block boundary, and tails of every length
+            for split in &[1, 7, 31, 32, 33, 63, 64, 65, 100, 1000] {
+                let mut c = Count::new();
+                let mut state = State::default();
+                let mut newlines = 0;
+                for piece in bytes.chunks(*split) {
+                    unsafe { count_fn(piece, &mut c, &mut state) };
+                    newlines += unsafe { newlines_fn(piece) };
+                }
+                assert_eq!(c, reference(&bytes), "{} in pieces of {}", name, split);
+                assert_eq!(newlines, c.newlines, "{} in pieces of {}", name, split);
+            }
+        }
+    }
+
+    #[test]
+    fn newline_count() {
+        let bytes = sample();
+        for len in (0..100).chain(vec![bytes.len()]) {
+            assert_eq!(newlines(&bytes[..len]), newlines_scalar(&bytes[..len]));
+            assert_eq!(newlines(&bytes[..len]), reference(&bytes[..len]).newlines);
+        }
+    }
+}
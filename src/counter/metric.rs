@@ -0,0 +1,456 @@
+use std::cmp::max;
+use std::error;
+use std::fmt;
+use options::Options;
+use super::display::{Display, Json};
+use super::{Count, Counter};
+
+/// A value computed from the bytes of an input, like the number of lines or of blank lines in it.
+///
+/// A metric is given the bytes of an input in order with `update()`, split at arbitrary points
+/// (even in the middle of a line or a UTF-8 character), so it has to keep whatever state it needs
+/// to handle that. The values for several inputs are combined into a total with `merge()`.
+///
+/// Metrics are counted together in a `Metrics` set. They have to be `Clone` so the set can be
+/// copied, and `Send` and `Sync` so it can be shared by the jobs counting files.
+pub trait Metric: Send + Sync + CloneMetric {
+    /// The name of the metric, used as the column header and the JSON member name.
+    fn name(&self) -> &str;
+
+    /// Count the next bytes of the input.
+    fn update(&mut self, bytes: &[u8]);
+
+    /// Return the value for the bytes seen so far.
+    fn value(&self) -> u64;
+
+    /// Combine the value of the same metric for another input into this one, for totals.
+    fn merge(&mut self, other: &dyn Metric);
+
+    /// Return a new instance of this metric with nothing counted yet.
+    fn empty(&self) -> Box<dyn Metric>;
+
+    /// Return the value formatted for display.
+    fn display(&self) -> String {
+        self.value().to_string()
+    }
+
+    /// Return `true` if the value is read from the standard counts with `read_count()`, instead
+    /// of counted from the bytes given to `update()`.
+    fn is_standard(&self) -> bool {
+        false
+    }
+
+    /// Read the value from the standard counts for the bytes seen so far. A `Metrics` set counts
+    /// these once for all of its metrics, and passes them on after every change.
+    fn read_count(&mut self, _count: &Count) {}
+}
+
+/// Copies a boxed metric. This is implemented for every metric that is `Clone`.
+pub trait CloneMetric {
+    fn clone_box(&self) -> Box<dyn Metric>;
+}
+
+impl<T: Metric + Clone + 'static> CloneMetric for T {
+    fn clone_box(&self) -> Box<dyn Metric> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Metric> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl<'a> fmt::Debug for dyn Metric + 'a {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name(), self.value())
+    }
+}
+
+/// Define one of the counts wc has always had as a metric. These don't count anything in
+/// `update()`, since counting each of them separately would mean going over the bytes once for
+/// each. Their value is read from the `Count` a `Metrics` set makes in a single pass instead.
+macro_rules! standard_metric {
+    ($(#[$doc:meta])* $metric:ident, $name:expr, $field:ident, $merge:expr) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq)]
+        pub struct $metric {
+            value: u64,
+        }
+
+        impl $metric {
+            pub const NAME: &'static str = $name;
+        }
+
+        impl Metric for $metric {
+            fn name(&self) -> &str {
+                $metric::NAME
+            }
+
+            fn update(&mut self, _bytes: &[u8]) {}
+
+            fn value(&self) -> u64 {
+                self.value
+            }
+
+            fn merge(&mut self, other: &dyn Metric) {
+                self.value = $merge(self.value, other.value());
+            }
+
+            fn empty(&self) -> Box<dyn Metric> {
+                Box::new($metric::default())
+            }
+
+            fn is_standard(&self) -> bool {
+                true
+            }
+
+            fn read_count(&mut self, count: &Count) {
+                self.value = count.$field;
+            }
+        }
+    };
+}
+
+fn sum(a: u64, b: u64) -> u64 {
+    a + b
+}
+
+standard_metric!(
+    /// The number of newlines.
+    Lines, "lines", newlines, sum);
+standard_metric!(
+    /// The number of words, which are runs of bytes separated by whitespace.
+    Words, "words", words, sum);
+standard_metric!(
+    /// The number of UTF-8 characters.
+    Chars, "chars", chars, sum);
+standard_metric!(
+    /// The number of bytes.
+    Bytes, "bytes", bytes, sum);
+standard_metric!(
+    /// The length of the longest line in bytes. Totals are the longest line of any input.
+    MaxLineLength, "max_line_length", max_line, max);
+
+/// An ordered set of metrics that are counted together. The standard counts are counted once for
+/// the whole set, in a single pass over the bytes (see `Counter`), and the standard metrics read
+/// their values from that.
+///
+/// An empty set is used as the prototype for the inputs, see `count_file()` and the other
+/// `count_*()` methods, and for the total, see `merge()`.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    counter: Counter,
+    /// Counts that weren't counted by `counter`, like the ones merged in from other inputs.
+    counted: Count,
+    metrics: Vec<Box<dyn Metric>>,
+}
+
+impl Metrics {
+    /// Return an empty set.
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Return the standard counts enabled in the options, in the order wc prints them.
+    pub fn standard(opts: &Options) -> Self {
+        let mut metrics = Metrics::new();
+        let standard: [(bool, Box<dyn Metric>); 5] = [
+            (opts.lines, Box::new(Lines::default())),
+            (opts.words, Box::new(Words::default())),
+            (opts.chars, Box::new(Chars::default())),
+            (opts.bytes, Box::new(Bytes::default())),
+            (opts.max_line, Box::new(MaxLineLength::default())),
+        ];
+        for (enabled, metric) in standard {
+            if enabled {
+                metrics.register(metric);
+            }
+        }
+        metrics
+    }
+
+    /// Return the standard counts enabled in the options, starting with the values in the given
+    /// count.
+    pub fn from_count(count: &Count, opts: &Options) -> Self {
+        Metrics::standard(opts).with_count(count)
+    }
+
+    /// Add a metric to the end of the set.
+    pub fn register(&mut self, mut metric: Box<dyn Metric>) {
+        metric.read_count(&self.count());
+        self.metrics.push(metric);
+    }
+
+    /// Count the next bytes of the input. The standard counts are counted once for all of them.
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.counter.update(bytes);
+        self.update_metrics(bytes);
+    }
+
+    /// Return the standard counts, including the ones that aren't in the set.
+    pub fn count(&self) -> Count {
+        self.counter.count() + self.counted
+    }
+
+    /// Combine the values from another set with the same metrics into this one, for totals.
+    pub fn merge(&mut self, other: &Metrics) -> Result<(), Mismatch> {
+        if self.names() != other.names() {
+            return Err(Mismatch { expected: self.names(), found: other.names() });
+        }
+
+        for (metric, other) in self.metrics.iter_mut().zip(&other.metrics) {
+            metric.merge(&**other);
+        }
+        self.add_count(&other.count());
+        Ok(())
+    }
+
+    /// Return a set with the same metrics and nothing counted yet.
+    pub fn empty(&self) -> Metrics {
+        Metrics {
+            metrics: self.metrics.iter().map(|metric| metric.empty()).collect(),
+            ..Metrics::default()
+        }
+    }
+
+    /// Return a set with the same metrics, the standard counts in `count`, and nothing counted
+    /// for the other metrics. This is for inputs that weren't read, or were only partly read.
+    pub fn with_count(&self, count: &Count) -> Metrics {
+        let mut metrics = self.empty();
+        metrics.add_count(count);
+        metrics
+    }
+
+    /// Return `true` if there are only standard metrics in the set, so they can be counted some
+    /// other way than reading every byte.
+    pub fn is_standard(&self) -> bool {
+        self.metrics.iter().all(|metric| metric.is_standard())
+    }
+
+    /// Return the metrics in the set, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Metric> {
+        self.metrics.iter().map(|metric| &**metric)
+    }
+
+    /// Return the first metric in the set with the given name.
+    pub fn get(&self, name: &str) -> Option<&dyn Metric> {
+        self.iter().find(|metric| metric.name() == name)
+    }
+
+    /// Return the names of the metrics in the set, in order.
+    pub fn names(&self) -> Vec<String> {
+        self.iter().map(|metric| metric.name().to_owned()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.metrics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metrics.is_empty()
+    }
+
+    pub fn display<'a>(&self, opts: &'a Options) -> Display<'a> {
+        Display::new(self, opts)
+    }
+
+    pub fn json<'a>(&self, opts: &'a Options) -> Json<'a> {
+        Json::new(opts).metrics(self)
+    }
+
+    /// Count the standard counts some other way than `update()`, like in parallel. The bytes
+    /// still have to be given to the other metrics with `update_metrics()`.
+    pub(super) fn add_count(&mut self, count: &Count) {
+        self.counted.aggregate(count);
+        self.read_count();
+    }
+
+    /// Give the bytes to the metrics without counting the standard counts, for bytes that were
+    /// counted with `add_count()`.
+    pub(super) fn update_metrics(&mut self, bytes: &[u8]) {
+        for metric in &mut self.metrics {
+            metric.update(bytes);
+        }
+        self.read_count();
+    }
+
+    /// Pass the standard counts on to the metrics that read them.
+    fn read_count(&mut self) {
+        let count = self.count();
+        for metric in &mut self.metrics {
+            metric.read_count(&count);
+        }
+    }
+}
+
+/// Returned by `Metrics::merge()` for sets that don't have the same values in the same order.
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub expected: Vec<String>,
+    pub found: Vec<String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "can't merge metrics [{}] into [{}]", self.found.join(", "), self.expected.join(", "))
+    }
+}
+
+impl error::Error for Mismatch {}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{Lines, MaxLineLength, Metric, Metrics, Mismatch};
+    use counter::Count;
+    use options::Options;
+
+    /// Counts lines that are empty or only whitespace.
+    #[derive(Clone, Default)]
+    pub struct BlankLines {
+        blank: u64,
+        line_has_text: bool,
+    }
+
+    impl Metric for BlankLines {
+        fn name(&self) -> &str { "blank_lines" }
+
+        fn update(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                if byte == b'\n' {
+                    if !self.line_has_text { self.blank += 1; }
+                    self.line_has_text = false;
+                }
+                else if !(byte as char).is_whitespace() {
+                    self.line_has_text = true;
+                }
+            }
+        }
+
+        fn value(&self) -> u64 { self.blank }
+
+        fn merge(&mut self, other: &dyn Metric) { self.blank += other.value(); }
+
+        fn empty(&self) -> Box<dyn Metric> { Box::<BlankLines>::default() }
+    }
+
+    fn values(metrics: &Metrics) -> Vec<(String, u64)> {
+        metrics.iter().map(|m| (m.name().to_owned(), m.value())).collect()
+    }
+
+    #[test]
+    fn standard() {
+        let opts = Options::test_empty().unwrap();
+        let mut metrics = Metrics::standard(&opts);
+        metrics.update(b"one two\nthree");
+        metrics.update(b" four\n");
+        assert_eq!(values(&metrics), vec![("lines".to_owned(), 2), ("words".to_owned(), 4), ("bytes".to_owned(), 19)]);
+
+        let opts = Options::test_args(vec!["-mL"]).unwrap();
+        let metrics = Metrics::standard(&opts);
+        assert_eq!(metrics.names(), vec!["chars", "max_line_length"]);
+    }
+
+    /// The standard counts are metrics like any other, so they can be registered in any order.
+    #[test]
+    fn standard_metrics() {
+        let mut metrics = Metrics::new();
+        metrics.register(Box::new(MaxLineLength::default()));
+        metrics.register(Box::new(BlankLines::default()));
+        metrics.register(Box::new(Lines::default()));
+        assert!(!metrics.is_standard());
+        metrics.update(b"one\n\nthree\n");
+        assert_eq!(values(&metrics), vec![
+            ("max_line_length".to_owned(), 5), ("blank_lines".to_owned(), 1), ("lines".to_owned(), 3),
+        ]);
+        assert_eq!(metrics.get(Lines::NAME).map(|metric| metric.value()), Some(3));
+        assert!(metrics.get("words").is_none());
+
+        let mut lines = Lines::default();
+        lines.merge(metrics.get(Lines::NAME).unwrap());
+        lines.merge(metrics.get(Lines::NAME).unwrap());
+        assert_eq!(lines.value(), 6);
+        let mut longest = MaxLineLength::default();
+        longest.merge(metrics.get(MaxLineLength::NAME).unwrap());
+        assert_eq!(longest.value(), 5);
+    }
+
+    #[test]
+    fn split_anywhere() {
+        let opts = Options::test_args(vec!["-lwmcL"]).unwrap();
+        let bytes = "some wörds\n\n  and 💖 more\twords\n".as_bytes();
+        let mut whole = Metrics::standard(&opts);
+        whole.register(Box::new(BlankLines::default()));
+        whole.update(bytes);
+        for i in 0..=bytes.len() {
+            let mut split = whole.empty();
+            split.update(&bytes[..i]);
+            split.update(&bytes[i..]);
+            assert_eq!(values(&split), values(&whole), "split at {}", i);
+        }
+    }
+
+    #[test]
+    fn from_count() {
+        let opts = Options::test_args(vec!["-lwmcL"]).unwrap();
+        let count = Count { newlines: 1, words: 2, chars: 3, bytes: 4, max_line: 5 };
+        let metrics = Metrics::from_count(&count, &opts);
+        assert_eq!(metrics.len(), 5);
+        assert_eq!(metrics.iter().map(|m| m.value()).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(metrics.count(), count);
+    }
+
+    #[test]
+    fn merge() {
+        let opts = Options::test_args(vec!["-lL"]).unwrap();
+        let mut total = Metrics::standard(&opts);
+        let mut metrics = total.empty();
+        metrics.update(b"a\nbcd\n");
+        total.merge(&metrics).unwrap();
+        let mut metrics = total.empty();
+        metrics.update(b"abcdef\n");
+        total.merge(&metrics).unwrap();
+        total.merge(&total.empty()).unwrap();
+        assert_eq!(values(&total), vec![("lines".to_owned(), 3), ("max_line_length".to_owned(), 6)]);
+    }
+
+    /// Sets with different metrics, or the same ones in a different order, can't be merged.
+    #[test]
+    fn merge_mismatch() {
+        let mut total = Metrics::standard(&Options::test_args(vec!["-lw"]).unwrap());
+        let other = Metrics::standard(&Options::test_args(vec!["-l"]).unwrap());
+        assert_eq!(total.merge(&other), Err(Mismatch {
+            expected: vec!["lines".to_owned(), "words".to_owned()],
+            found: vec!["lines".to_owned()],
+        }));
+
+        let mut other = total.empty();
+        other.register(Box::new(BlankLines::default()));
+        assert!(total.merge(&other).is_err());
+        assert_eq!(total.merge(&other).unwrap_err().to_string(),
+                   "can't merge metrics [lines, words, blank_lines] into [lines, words]");
+    }
+
+    #[test]
+    fn custom() {
+        let opts = Options::test_args(vec!["-l"]).unwrap();
+        let mut metrics = Metrics::standard(&opts);
+        metrics.register(Box::new(BlankLines::default()));
+        assert!(!metrics.is_standard());
+        assert!(Metrics::standard(&opts).is_standard());
+        metrics.update(b"one\n\n  \n");
+        metrics.update(b"two\n\n");
+        assert_eq!(values(&metrics), vec![("lines".to_owned(), 5), ("blank_lines".to_owned(), 3)]);
+
+        let mut total = metrics.empty();
+        assert_eq!(values(&total), vec![("lines".to_owned(), 0), ("blank_lines".to_owned(), 0)]);
+        total.merge(&metrics).unwrap();
+        total.merge(&metrics).unwrap();
+        assert_eq!(values(&total), vec![("lines".to_owned(), 10), ("blank_lines".to_owned(), 6)]);
+
+        // only the standard counts can be filled in without reading the input
+        let metrics = metrics.with_count(&Count { newlines: 7, ..Count::new() });
+        assert_eq!(values(&metrics), vec![("lines".to_owned(), 7), ("blank_lines".to_owned(), 0)]);
+    }
+}
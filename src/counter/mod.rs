@@ -1,4 +1,21 @@
+mod archive;
 mod count;
+mod decompress;
 mod display;
+mod error;
+mod kernel;
+mod metric;
+mod span;
+mod stream;
+mod template;
 
+pub use self::archive::{is_archive, Member, MemberResult};
 pub use self::count::Count;
+pub use self::decompress::Compression;
+pub(crate) use self::display::metadata;
+pub use self::display::{plan_width, Delimited, Json, JsonString, Separator};
+pub use self::error::Error;
+pub use self::metric::{Bytes, Chars, CloneMetric, Lines, MaxLineLength, Metric, Metrics, Mismatch, Words};
+pub use self::span::Span;
+pub use self::stream::Counter;
+pub use self::template::{Printf, Template};
@@ -0,0 +1,162 @@
+use std::cmp::max;
+use std::ops::Add;
+use super::Count;
+use super::kernel;
+
+/// Counts for a range of bytes, along with how the range starts and ends so it can be joined
+/// with the counts for the ranges next to it.
+///
+/// Joining the spans for consecutive ranges with `+` gives exactly the counts that counting all of
+/// the bytes in one pass would give. Characters are counted by their leading byte, so a character
+/// that is split between two ranges is counted once without any help.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    /// Counts for the bytes in this range on their own.
+    pub count: Count,
+    /// `true` if the first byte is part of a word.
+    pub starts_in_word: bool,
+    /// `true` if the last byte is part of a word.
+    pub ends_in_word: bool,
+    /// The number of bytes before the first newline.
+    pub head_line: u64,
+    /// The number of bytes after the last newline.
+    pub tail_line: u64,
+}
+
+impl Span {
+    /// Return an empty span.
+    pub fn new() -> Self {
+        Span {
+            count: Count::new(),
+            starts_in_word: false,
+            ends_in_word: false,
+            head_line: 0,
+            tail_line: 0,
+        }
+    }
+
+    /// Generate the span for the given bytes.
+    pub fn from_slice(bytes: &[u8]) -> Self {
+        let mut span = Span::new();
+        let mut state = kernel::State::default();
+        kernel::count(bytes, &mut span.count, &mut state);
+
+        span.starts_in_word = bytes.first().is_some_and(|b| !kernel::is_space(*b));
+        span.ends_in_word = state.in_word;
+        span.head_line = bytes.iter().position(|b| *b == b'\n').unwrap_or(bytes.len()) as u64;
+        span.tail_line = state.line_length;
+        span
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count.bytes == 0
+    }
+}
+
+impl Default for Span {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Add for Span {
+    type Output = Span;
+
+    /// Join this span with the span for the bytes that immediately follow it.
+    fn add(self, next: Self) -> Self::Output {
+        if self.is_empty() { return next; }
+        if next.is_empty() { return self; }
+
+        // a word that crosses the boundary was counted once on each side
+        let split_word = self.ends_in_word && next.starts_in_word;
+
+        let count = Count {
+            newlines: self.count.newlines + next.count.newlines,
+            words: self.count.words + next.count.words - split_word as u64,
+            bytes: self.count.bytes + next.count.bytes,
+            chars: self.count.chars + next.count.chars,
+            max_line: max(max(self.count.max_line, next.count.max_line),
+                          self.tail_line + next.head_line),
+        };
+
+        // if there is no newline on one side, the partial line continues across it
+        let head_line = if self.count.newlines > 0 { self.head_line }
+            else { self.count.bytes + next.head_line };
+        let tail_line = if next.count.newlines > 0 { next.tail_line }
+            else { self.tail_line + next.count.bytes };
+
+        Span {
+            count,
+            starts_in_word: self.starts_in_word,
+            ends_in_word: next.ends_in_word,
+            head_line,
+            tail_line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Span;
+
+    fn sample() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend("\u{80}\u{800}".bytes().skip(1)); // start in the middle of a character
+        for i in 0..20 {
+            bytes.extend("word ".repeat(i).bytes());
+            bytes.extend("இঈஇ💖\t".bytes());
+            if i % 4 == 0 { bytes.extend("\n\n".bytes()); }
+        }
+        bytes.extend("💖".bytes().take(2)); // end in the middle of a character
+        bytes
+    }
+
+    #[test]
+    fn split_anywhere() {
+        let bytes = sample();
+        let whole = Span::from_slice(&bytes);
+        for i in 0..=bytes.len() {
+            let joined = Span::from_slice(&bytes[..i]) + Span::from_slice(&bytes[i..]);
+            assert_eq!(joined, whole, "split at {}", i);
+        }
+    }
+
+    #[test]
+    fn split_many() {
+        let bytes = sample();
+        let whole = Span::from_slice(&bytes);
+        for size in 1..10 {
+            let joined = bytes.chunks(size)
+                .map(Span::from_slice)
+                .fold(Span::new(), |a, b| a + b);
+            assert_eq!(joined, whole, "chunk size {}", size);
+        }
+    }
+
+    #[test]
+    fn boundaries() {
+        let span = Span::from_slice(b"one\ntwo three\nfour");
+        assert!(span.starts_in_word);
+        assert!(span.ends_in_word);
+        assert_eq!(span.head_line, 3);
+        assert_eq!(span.tail_line, 4);
+        assert_eq!(span.count.max_line, 9);
+    }
+
+    /// A character split between two spans is only counted by the span with its leading byte.
+    #[test]
+    fn character_across_boundary() {
+        let bytes = "a💖b".as_bytes();
+        for i in 1..5 {
+            let span = Span::from_slice(&bytes[..i]) + Span::from_slice(&bytes[i..]);
+            assert_eq!(span.count.chars, 3, "split at {}", i);
+        }
+    }
+
+    #[test]
+    fn word_across_boundary() {
+        let span = Span::from_slice(b"a wo") + Span::from_slice(b"rd b");
+        assert_eq!(span.count.words, 3);
+        assert_eq!(span.count.max_line, 8);
+    }
+}
@@ -0,0 +1,115 @@
+use std::io;
+use super::kernel;
+use super::Count;
+
+/// Generates counts for bytes handed to it a chunk at a time, for data that isn't in a file, like
+/// a socket or the other end of an `io::copy()`.
+///
+/// Chunks can be split anywhere, even in the middle of a word or a UTF-8 character, the counts are
+/// the same as for all of the bytes at once.
+///
+/// ```
+/// use std::io;
+/// use rust_wc::counter::Counter;
+///
+/// let mut counter = Counter::new();
+/// counter.update(b"one tw");
+/// io::copy(&mut &b"o three\n"[..], &mut counter).unwrap();
+/// let count = counter.finish();
+/// assert_eq!((count.newlines, count.words, count.bytes), (1, 3, 14));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Counter {
+    count: Count,
+    state: kernel::State,
+}
+
+impl Counter {
+    pub fn new() -> Self {
+        Counter::default()
+    }
+
+    /// Count the next chunk of bytes.
+    pub fn update(&mut self, bytes: &[u8]) {
+        kernel::count(bytes, &mut self.count, &mut self.state);
+    }
+
+    /// Return the counts for everything so far, without finishing.
+    pub fn count(&self) -> Count {
+        self.count
+    }
+
+    /// Return the counts for all of the bytes.
+    pub fn finish(self) -> Count {
+        self.count
+    }
+}
+
+impl io::Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Counter;
+    use counter::Count;
+    use std::io;
+    use std::io::prelude::*;
+
+    fn text() -> Vec<u8> {
+        "some wörds\n\n  and 💖 more\twords\nno newline at the end".repeat(10).into_bytes()
+    }
+
+    #[test]
+    fn split_anywhere() {
+        let bytes = text();
+        let mut whole = Counter::new();
+        whole.update(&bytes);
+        let whole = whole.finish();
+        assert_eq!(whole.newlines, 30);
+
+        for i in 0..=bytes.len() {
+            let mut counter = Counter::new();
+            counter.update(&bytes[..i]);
+            counter.update(&bytes[i..]);
+            assert_eq!(counter.finish(), whole, "split at {}", i);
+        }
+
+        for size in 1..10 {
+            let mut counter = Counter::new();
+            for chunk in bytes.chunks(size) {
+                counter.update(chunk);
+            }
+            assert_eq!(counter.finish(), whole, "chunk size {}", size);
+        }
+    }
+
+    #[test]
+    fn write() {
+        let bytes = text();
+        let mut counter = Counter::new();
+        counter.update(&bytes);
+        let expected = counter.finish();
+
+        let mut counter = Counter::new();
+        io::copy(&mut &bytes[..], &mut counter).unwrap();
+        assert_eq!(counter.count(), expected);
+
+        let mut counter = Counter::new();
+        write!(counter, "{}", String::from_utf8(bytes).unwrap()).unwrap();
+        counter.flush().unwrap();
+        assert_eq!(counter.finish(), expected);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(Counter::new().finish(), Count::new());
+    }
+}
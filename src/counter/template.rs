@@ -0,0 +1,221 @@
+use std::fmt;
+use counter::{Bytes, Chars, Lines, MaxLineLength, Metrics, Words};
+
+/// A user defined output format, given with `--printf`.
+///
+/// Directives start with `%` and are replaced with a value for each input:
+///
+/// - `%l` newlines
+/// - `%w` words
+/// - `%m` characters
+/// - `%c` bytes
+/// - `%L` maximum line length
+/// - `%n` the file name
+/// - `%e` the error message, if the input couldn't be counted
+/// - `%%` a literal `%`
+///
+/// The counts are looked up by name in the set of metrics an input was counted for, so it has to
+/// have the ones in `metrics()`.
+///
+/// A directive can have a field width between the `%` and the letter (`%7l`), and a `-` before
+/// the width left aligns the value (`%-20n`). The escapes `\n`, `\t`, `\r`, `\0`, and `\\` are
+/// also supported.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    Literal(String),
+    Field {
+        field: Field,
+        width: usize,
+        left: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    /// The value of the metric with the given name.
+    Metric(&'static str),
+    Name,
+    Error,
+}
+
+impl Template {
+    /// Parse a template, returning a description of the problem if it isn't valid.
+    pub fn parse(format: &str) -> Result<Template, String> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => literal.push('\n'),
+                    Some('t') => literal.push('\t'),
+                    Some('r') => literal.push('\r'),
+                    Some('0') => literal.push('\0'),
+                    Some('\\') => literal.push('\\'),
+                    // unknown escapes are kept as they are
+                    Some(c) => { literal.push('\\'); literal.push(c); }
+                    None => literal.push('\\'),
+                },
+                '%' => {
+                    if chars.peek() == Some(&'%') {
+                        chars.next();
+                        literal.push('%');
+                        continue;
+                    }
+
+                    let left = chars.peek() == Some(&'-');
+                    if left { chars.next(); }
+                    let mut width = String::new();
+                    while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()).cloned() {
+                        width.push(d);
+                        chars.next();
+                    }
+                    let width = if width.is_empty() { 0 }
+                        else { width.parse().map_err(|_| format!("field width too large: '{}'", width))? };
+
+                    let field = match chars.next() {
+                        Some('l') => Field::Metric(Lines::NAME),
+                        Some('w') => Field::Metric(Words::NAME),
+                        Some('m') => Field::Metric(Chars::NAME),
+                        Some('c') => Field::Metric(Bytes::NAME),
+                        Some('L') => Field::Metric(MaxLineLength::NAME),
+                        Some('n') => Field::Name,
+                        Some('e') => Field::Error,
+                        Some(c) => return Err(format!("unknown directive '%{}'", c)),
+                        None => return Err("incomplete directive at the end".to_owned()),
+                    };
+
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(literal));
+                        literal = String::new();
+                    }
+                    pieces.push(Piece::Field { field, width, left });
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Ok(Template { pieces })
+    }
+
+    /// Return the names of the metrics the template uses, in the order they first appear.
+    pub fn metrics(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        for piece in &self.pieces {
+            if let Piece::Field { field: Field::Metric(name), .. } = *piece {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    /// Return something that displays the template filled in for the given input. Counts are
+    /// shown as zero if there are no metrics, or if the metrics don't have the count.
+    pub fn render<'a>(&'a self, file: &'a str, metrics: Option<&'a Metrics>, error: Option<&'a dyn fmt::Display>) -> Printf<'a> {
+        Printf {
+            template: self,
+            file,
+            metrics,
+            error,
+        }
+    }
+}
+
+/// Displays a `Template` filled in for an input.
+pub struct Printf<'a> {
+    template: &'a Template,
+    file: &'a str,
+    metrics: Option<&'a Metrics>,
+    error: Option<&'a dyn fmt::Display>,
+}
+
+impl<'a> fmt::Display for Printf<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for piece in &self.template.pieces {
+            let (field, width, left) = match *piece {
+                Piece::Literal(ref literal) => { write!(f, "{}", literal)?; continue; }
+                Piece::Field { field, width, left } => (field, width, left),
+            };
+
+            let value = match field {
+                Field::Metric(name) => match self.metrics.and_then(|metrics| metrics.get(name)) {
+                    Some(metric) => metric.display(),
+                    None => "0".to_owned(),
+                },
+                Field::Name => self.file.to_owned(),
+                Field::Error => self.error.map(|e| e.to_string()).unwrap_or_default(),
+            };
+            if left { write!(f, "{: <width$}", value, width = width)?; }
+            else { write!(f, "{: >width$}", value, width = width)?; }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Template;
+    use counter::{Count, Metrics};
+    use options::Options;
+
+    fn count() -> Metrics {
+        let opts = Options::test_args(vec!["-lwmcL"]).unwrap();
+        Metrics::from_count(&Count { newlines: 1, words: 2, chars: 3, bytes: 4, max_line: 5 }, &opts)
+    }
+
+    #[test]
+    fn directives() {
+        let template = Template::parse("%l %w %m %c %L %n %e|100%%").unwrap();
+        let count = count();
+        assert_eq!(template.render("file", Some(&count), None).to_string(), "1 2 3 4 5 file |100%");
+    }
+
+    #[test]
+    fn escapes() {
+        let template = Template::parse(r"%l\t%w\t%n\n\\\q").unwrap();
+        let count = count();
+        assert_eq!(template.render("file", Some(&count), None).to_string(), "1\t2\tfile\n\\\\q");
+    }
+
+    #[test]
+    fn width() {
+        let template = Template::parse("[%5l][%-6n][%2c]").unwrap();
+        let count = count();
+        assert_eq!(template.render("file", Some(&count), None).to_string(), "[    1][file  ][ 4]");
+    }
+
+    #[test]
+    fn error() {
+        let template = Template::parse("%n: %l (%e)").unwrap();
+        let error = "Is a directory";
+        assert_eq!(template.render("dir", None, Some(&error)).to_string(), "dir: 0 (Is a directory)");
+    }
+
+    #[test]
+    fn metrics() {
+        let template = Template::parse("%c %l %n %5l %e").unwrap();
+        assert_eq!(template.metrics(), vec!["bytes", "lines"]);
+
+        // a count that isn't in the set is zero
+        let opts = Options::test_args(vec!["-l"]).unwrap();
+        let metrics = Metrics::from_count(&Count { newlines: 1, bytes: 4, ..Count::new() }, &opts);
+        assert_eq!(template.render("file", Some(&metrics), None).to_string(), "0 1 file     1 ");
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(Template::parse("%x"), Err("unknown directive '%x'".to_owned()));
+        assert_eq!(Template::parse("%l %"), Err("incomplete directive at the end".to_owned()));
+        assert_eq!(Template::parse("%-5"), Err("incomplete directive at the end".to_owned()));
+    }
+}
@@ -0,0 +1,163 @@
+//! `--include` and `--exclude` filters for the files to count.
+
+use glob::Glob;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Pattern {
+    glob: Glob,
+    /// Patterns without a `/` are matched against the last part of the path.
+    name_only: bool,
+    /// Patterns starting with a `/` are matched against the whole path, the others against the
+    /// part below the directory being walked.
+    anchored: bool,
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Pattern {
+        let name_only = !pattern.contains('/');
+        let anchored = pattern.starts_with('/');
+        let glob = if name_only || anchored { Glob::new(pattern) }
+            else { Glob::new(pattern).in_any_dir() };
+        Pattern { glob, name_only, anchored }
+    }
+
+    fn is_match(&self, path: &str, relative: &str) -> bool {
+        if self.name_only {
+            let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or(path);
+            self.glob.is_match(name)
+        }
+        else if self.anchored {
+            self.glob.is_match(path)
+        }
+        else {
+            self.glob.is_match(relative)
+        }
+    }
+}
+
+/// Decides which files are counted, based on glob patterns (see the `glob` module).
+///
+/// A pattern without a `/` is matched against the file name. A pattern with one is matched against
+/// the end of the path, starting at a directory, so `tests/**` matches both `tests/it.rs` and
+/// `crate/tests/it.rs`; start the pattern with a `/` to match the whole path instead. For files
+/// found in a directory with `--recursive`, only the part of the path below that directory is
+/// matched, so the directories it is in don't matter. A file is counted if it matches one of the
+/// include patterns (or there aren't any) and none of the exclude patterns.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    pub fn new<S: AsRef<str>>(include: &[S], exclude: &[S]) -> Filter {
+        Filter {
+            include: include.iter().map(|p| Pattern::new(p.as_ref())).collect(),
+            exclude: exclude.iter().map(|p| Pattern::new(p.as_ref())).collect(),
+        }
+    }
+
+    /// Return `true` if there are no patterns, so every file is counted.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Return `true` if the given file should be counted.
+    pub fn is_match(&self, file: &str) -> bool {
+        self.is_match_below(file, file)
+    }
+
+    /// Return `true` if a file found in a directory should be counted. `relative` is its path
+    /// below the directory.
+    pub fn is_match_below(&self, file: &str, relative: &str) -> bool {
+        let (file, relative) = (strip_dot(file), strip_dot(relative));
+        (self.include.is_empty() || self.include.iter().any(|p| p.is_match(file, relative)))
+            && !self.is_excluded_below(file, relative)
+    }
+
+    /// Return `true` if a file or directory found in a directory matches one of the exclude
+    /// patterns. `relative` is its path below the directory, like for `is_match_below()`. Nothing
+    /// inside of an excluded directory is counted.
+    pub fn is_excluded_below(&self, path: &str, relative: &str) -> bool {
+        let (path, relative) = (strip_dot(path), strip_dot(relative));
+        self.exclude.iter().any(|p| p.is_match(path, relative))
+    }
+}
+
+fn strip_dot(mut path: &str) -> &str {
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest.trim_start_matches('/');
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+
+    #[test]
+    fn empty() {
+        let filter = Filter::new::<&str>(&[], &[]);
+        assert!(filter.is_empty());
+        assert!(filter.is_match("anything"));
+    }
+
+    #[test]
+    fn include() {
+        let filter = Filter::new(&["*.rs", "*.toml"], &[]);
+        assert!(!filter.is_empty());
+        assert!(filter.is_match("main.rs"));
+        assert!(filter.is_match("src/counter/count.rs"));
+        assert!(filter.is_match("./Cargo.toml"));
+        assert!(!filter.is_match("README.md"));
+    }
+
+    #[test]
+    fn exclude() {
+        let filter = Filter::new(&[], &["tests/**", "*.generated.rs"]);
+        assert!(filter.is_match("src/main.rs"));
+        assert!(!filter.is_match("tests/it.rs"));
+        assert!(!filter.is_match("./tests/data/big.txt"));
+        assert!(!filter.is_match("src/parser.generated.rs"));
+        // patterns with a `/` match the end of the path, starting at a directory
+        assert!(!filter.is_match("crate/tests/it.rs"));
+        assert!(filter.is_match("crate/my-tests/it.rs"));
+
+        let filter = Filter::new(&[], &["/tmp/**"]);
+        assert!(!filter.is_match("/tmp/file"));
+        assert!(filter.is_match("/home/tmp/file"));
+    }
+
+    #[test]
+    fn exclude_wins() {
+        let filter = Filter::new(&["*.rs"], &["src/gen/**"]);
+        assert!(filter.is_match("src/main.rs"));
+        assert!(!filter.is_match("src/gen/parser.rs"));
+        assert!(!filter.is_match("src/main.c"));
+    }
+
+    #[test]
+    fn excluded_dirs() {
+        let filter = Filter::new(&["*.rs"], &["target", "src/gen"]);
+        assert!(filter.is_excluded_below("./target", "target"));
+        assert!(filter.is_excluded_below("./sub/target", "sub/target"));
+        assert!(filter.is_excluded_below("./src/gen", "src/gen"));
+        assert!(filter.is_excluded_below("crate/src/gen", "src/gen"));
+        assert!(!filter.is_excluded_below("./src", "src"));
+    }
+
+    /// Only the part of the path below the directory being walked is matched, except by patterns
+    /// that start with a `/`.
+    #[test]
+    fn below() {
+        let filter = Filter::new(&[], &["tests/**", "/tmp/**"]);
+        assert!(filter.is_match_below("some/tests/dir/it.rs", "it.rs"));
+        assert!(!filter.is_match_below("some/tests/dir/tests/it.rs", "tests/it.rs"));
+        assert!(!filter.is_excluded_below("some/tests/dir/sub", "sub"));
+        assert!(filter.is_excluded_below("some/tests/dir/tests/sub", "tests/sub"));
+        assert!(!filter.is_match_below("/tmp/dir/file", "file"));
+
+        let filter = Filter::new(&["*.rs"], &[]);
+        assert!(filter.is_match_below("tests/dir/it.rs", "it.rs"));
+    }
+}
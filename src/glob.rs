@@ -0,0 +1,334 @@
+//! Shell style glob patterns for matching paths.
+//!
+//! - `*` matches any sequence of characters except `/`
+//! - `?` matches any single character except `/`
+//! - `[abc]`, `[a-z]`, and `[!abc]` (or `[^abc]`) match a single character from (or not from) a
+//!   class
+//! - `**/` at the start of a pattern, or `/**/` in the middle, matches zero or more directories
+//! - `/**` at the end of a pattern matches everything inside of a directory
+//! - `\` matches the character after it literally
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `/**` at the end, anything at all
+    Rest,
+    /// `**/`, zero or more directories
+    Dirs,
+    /// `[...]`
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// A compiled glob pattern.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glob {
+    /// One list of tokens for each alternative after braces are expanded.
+    alternatives: Vec<Vec<Token>>,
+}
+
+impl Glob {
+    /// Compile the given pattern. A `[` without a matching `]` is treated as a literal `[`, and
+    /// the same goes for a `{` without a matching `}` or without a `,` inside.
+    pub fn new(pattern: &str) -> Glob {
+        let chars: Vec<char> = pattern.chars().collect();
+        let alternatives = expand_braces(&chars).iter()
+            .map(|alternative| tokenize(alternative))
+            .collect();
+        Glob { alternatives }
+    }
+
+    /// Return `true` if the pattern matches the whole path.
+    pub fn is_match(&self, path: &str) -> bool {
+        let path: Vec<char> = path.chars().collect();
+        self.alternatives.iter().any(|tokens| matches(tokens, &path))
+    }
+
+    /// Return a pattern that matches this one in any directory, like `**/` followed by this
+    /// pattern.
+    pub fn in_any_dir(mut self) -> Glob {
+        for tokens in &mut self.alternatives {
+            if tokens.first() != Some(&Token::Dirs) {
+                tokens.insert(0, Token::Dirs);
+            }
+        }
+        self
+    }
+}
+
+/// Expand the first brace group in the pattern, and then the groups in each of the results.
+fn expand_braces(chars: &[char]) -> Vec<Vec<char>> {
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => if let Some((end, commas)) = find_group(&chars[i..]) {
+                let (before, after) = (&chars[..i], &chars[i + end + 1..]);
+                let mut expanded = Vec::new();
+                let mut start = i + 1;
+                for comma in commas.into_iter().map(|c| i + c).chain(Some(i + end)) {
+                    // there are no groups before this one, but there can be more in the rest
+                    let mut rest = chars[start..comma].to_vec();
+                    rest.extend_from_slice(after);
+                    for mut tail in expand_braces(&rest) {
+                        let mut alternative = before.to_vec();
+                        alternative.append(&mut tail);
+                        expanded.push(alternative);
+                    }
+                    start = comma + 1;
+                }
+                return expanded;
+            },
+            _ => {}
+        }
+        i += 1;
+    }
+    vec![chars.to_vec()]
+}
+
+/// Find the end of the brace group at the start of `chars`, returning the position of the
+/// closing `}` and of each top level `,`, or `None` if it isn't a group.
+fn find_group(chars: &[char]) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return if commas.is_empty() { None } else { Some((i, commas)) };
+                }
+            }
+            ',' if depth == 1 => commas.push(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn tokenize(chars: &[char]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                tokens.push(Token::Char(chars[i + 1]));
+                i += 2;
+                continue;
+            }
+            '?' => tokens.push(Token::Any),
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start = i == 0 || chars[i - 1] == '/';
+                let at_end = i + 2 == chars.len();
+                let before_slash = chars.get(i + 2) == Some(&'/');
+                if at_start && before_slash {
+                    // `**/` (the `/` is part of the token)
+                    tokens.push(Token::Dirs);
+                    i += 3;
+                    continue;
+                }
+                else if at_start && at_end {
+                    // `/**` (or `**` on its own) matches everything
+                    tokens.push(Token::Rest);
+                }
+                else {
+                    // anywhere else it is the same as `*`
+                    tokens.push(Token::Star);
+                }
+                i += 2;
+                continue;
+            }
+            '*' => tokens.push(Token::Star),
+            '[' => match parse_class(&chars[i..]) {
+                Some((token, len)) => {
+                    tokens.push(token);
+                    i += len;
+                    continue;
+                }
+                None => tokens.push(Token::Char('[')),
+            },
+            c => tokens.push(Token::Char(c)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Parse a character class at the start of `chars`, returning the token and the number of
+/// characters used, or `None` if the class isn't closed.
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 1;
+    let negated = match chars.get(i) {
+        Some(&'!') | Some(&'^') => { i += 1; true }
+        _ => false,
+    };
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = match chars.get(i) {
+            None => return None,
+            // a `]` right at the start is part of the class
+            Some(&']') if !first => return Some((Token::Class { negated, ranges }, i + 1)),
+            Some(&'\\') => { i += 1; *chars.get(i)? }
+            Some(c) => *c,
+        };
+        first = false;
+        i += 1;
+
+        if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|c| *c != ']') {
+            let mut end = chars[i + 1];
+            i += 2;
+            if end == '\\' {
+                end = *chars.get(i)?;
+                i += 1;
+            }
+            ranges.push((c, end));
+        }
+        else {
+            ranges.push((c, c));
+        }
+    }
+}
+
+fn matches(tokens: &[Token], path: &[char]) -> bool {
+    let token = match tokens.first() {
+        Some(token) => token,
+        None => return path.is_empty(),
+    };
+    let rest = &tokens[1..];
+
+    match *token {
+        Token::Char(c) => path.first() == Some(&c) && matches(rest, &path[1..]),
+        Token::Any => path.first().is_some_and(|c| *c != '/') && matches(rest, &path[1..]),
+        Token::Class { negated, ref ranges } => match path.first() {
+            Some(&c) if c != '/' => {
+                let in_class = ranges.iter().any(|&(start, end)| start <= c && c <= end);
+                in_class != negated && matches(rest, &path[1..])
+            }
+            _ => false,
+        },
+        Token::Star => {
+            // try every length up to the next `/`
+            for i in 0..=path.len() {
+                if matches(rest, &path[i..]) {
+                    return true;
+                }
+                if i < path.len() && path[i] == '/' {
+                    break;
+                }
+            }
+            false
+        }
+        Token::Rest => true,
+        Token::Dirs => {
+            // try skipping zero or more whole directories
+            for i in 0..=path.len() {
+                if (i == 0 || path[i - 1] == '/') && matches(rest, &path[i..]) {
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Glob;
+
+    fn is_match(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).is_match(path)
+    }
+
+    #[test]
+    fn literal() {
+        assert!(is_match("abc", "abc"));
+        assert!(!is_match("abc", "abcd"));
+        assert!(!is_match("abc", "ab"));
+        assert!(is_match("a\\*c", "a*c"));
+        assert!(!is_match("a\\*c", "abc"));
+    }
+
+    #[test]
+    fn star() {
+        assert!(is_match("*.rs", "main.rs"));
+        assert!(is_match("*.rs", ".rs"));
+        assert!(!is_match("*.rs", "src/main.rs"));
+        assert!(is_match("src/*.rs", "src/main.rs"));
+        assert!(is_match("a*b*c", "aXbYc"));
+        assert!(!is_match("a*b*c", "aXbY"));
+    }
+
+    #[test]
+    fn question() {
+        assert!(is_match("?.rs", "a.rs"));
+        assert!(!is_match("?.rs", "ab.rs"));
+        assert!(!is_match("a?b", "a/b"));
+    }
+
+    #[test]
+    fn class() {
+        assert!(is_match("[abc].rs", "b.rs"));
+        assert!(!is_match("[abc].rs", "d.rs"));
+        assert!(is_match("[a-c0-9]", "7"));
+        assert!(is_match("[!a-c]", "d"));
+        assert!(is_match("[^a-c]", "d"));
+        assert!(!is_match("[!a-c]", "b"));
+        assert!(is_match("[]]", "]"));
+        assert!(is_match("[a-]", "-"));
+        assert!(!is_match("[!a]", "/"));
+        // not closed, so a literal `[`
+        assert!(is_match("[ab", "[ab"));
+    }
+
+    #[test]
+    fn double_star() {
+        assert!(is_match("**/foo", "foo"));
+        assert!(is_match("**/foo", "a/b/foo"));
+        assert!(!is_match("**/foo", "a/bfoo"));
+        assert!(is_match("a/**/b", "a/b"));
+        assert!(is_match("a/**/b", "a/x/y/b"));
+        assert!(!is_match("a/**/b", "ab"));
+        assert!(is_match("a/**", "a/x"));
+        assert!(is_match("a/**", "a/x/y"));
+        assert!(!is_match("a/**", "a"));
+        assert!(is_match("**", "a/b/c"));
+        // anywhere else `**` is the same as `*`
+        assert!(is_match("a**b", "axxb"));
+        assert!(!is_match("a**b", "a/b"));
+    }
+
+    #[test]
+    fn braces() {
+        assert!(is_match("*.{rs,toml}", "main.rs"));
+        assert!(is_match("*.{rs,toml}", "Cargo.toml"));
+        assert!(!is_match("*.{rs,toml}", "README.md"));
+        assert!(is_match("{src,tests}/**/*.rs", "tests/a/b.rs"));
+        assert!(is_match("a{b,c{d,e}}f", "acef"));
+        assert!(is_match("{a,b}{c,d}", "bc"));
+        assert!(is_match("{,x}y", "y"));
+        // not groups, so literal
+        assert!(is_match("{a}", "{a}"));
+        assert!(is_match("{a,b", "{a,b"));
+        assert!(is_match("\\{a,b}", "{a,b}"));
+    }
+
+    #[test]
+    fn in_any_dir() {
+        let glob = Glob::new("*.rs").in_any_dir();
+        assert!(glob.is_match("main.rs"));
+        assert!(glob.is_match("src/counter/count.rs"));
+        assert!(!glob.is_match("src/main.c"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Counts for groups of files, for `--group-by`.
+
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use counter::{Metrics, Mismatch};
+use options::GroupBy;
+
+/// The key for files without an extension.
+pub const NO_EXTENSION: &str = "(none)";
+
+/// Keeps a total for each group of files, in key order.
+#[derive(Debug)]
+pub struct Groups {
+    by: GroupBy,
+    counts: BTreeMap<OsString, Metrics>,
+}
+
+impl Groups {
+    pub fn new(by: GroupBy) -> Self {
+        Groups {
+            by,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Add the counts for a file to the total for its group. A new group starts out with the same
+    /// metrics as its first file, and the files after that have to have the same metrics.
+    pub fn add<P: AsRef<Path>>(&mut self, file: P, metrics: &Metrics) -> Result<(), Mismatch> {
+        let key = key(self.by, file.as_ref());
+        self.counts.entry(key)
+            .or_insert_with(|| metrics.empty())
+            .merge(metrics)
+    }
+
+    /// Return the groups and their totals, in key order.
+    pub fn iter(&self) -> btree_map::Iter<'_, OsString, Metrics> {
+        self.counts.iter()
+    }
+}
+
+/// Return the group the file belongs in.
+///
+/// - `Ext` is the extension without the `.`, or `(none)`. A `.` at the start of the name (like
+///   `.gitignore`) doesn't start an extension.
+/// - `Dir` is the directory the file is in, or `.` for a file in the current directory.
+/// - `Depth(n)` is the first `n` directories of that, so with a depth of 1 everything under
+///   `src/` is in the `src` group.
+///
+/// A leading `./` is ignored for all of these.
+fn key(by: GroupBy, file: &Path) -> OsString {
+    let file: PathBuf = file.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect();
+    let dir = match file.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+
+    match by {
+        GroupBy::Ext => match file.extension() {
+            Some(ext) if !ext.is_empty() => ext.to_owned(),
+            _ => OsString::from(NO_EXTENSION),
+        },
+        GroupBy::Dir => dir.as_os_str().to_owned(),
+        GroupBy::Depth(depth) => {
+            // the root isn't a level of its own, `/usr/share` at depth 1 is `/usr`
+            let mut normal = 0;
+            let prefix: PathBuf = dir.components()
+                .take_while(|component| match *component {
+                    Component::Normal(_) | Component::ParentDir => { normal += 1; normal <= depth }
+                    _ => true,
+                })
+                .collect();
+            prefix.into_os_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{key, Groups};
+    use counter::{Count, Metrics};
+    use options::{GroupBy, Options};
+    use std::path::Path;
+
+    #[test]
+    fn ext() {
+        assert_eq!(key(GroupBy::Ext, Path::new("src/main.rs")), "rs");
+        assert_eq!(key(GroupBy::Ext, Path::new("archive.tar.gz")), "gz");
+        assert_eq!(key(GroupBy::Ext, Path::new("Makefile")), "(none)");
+        assert_eq!(key(GroupBy::Ext, Path::new("./.gitignore")), "(none)");
+        assert_eq!(key(GroupBy::Ext, Path::new("file.")), "(none)");
+        assert_eq!(key(GroupBy::Ext, Path::new("a.d/file")), "(none)");
+        assert_eq!(key(GroupBy::Ext, Path::new("-")), "(none)");
+    }
+
+    #[test]
+    fn dir() {
+        assert_eq!(key(GroupBy::Dir, Path::new("src/counter/count.rs")), "src/counter");
+        assert_eq!(key(GroupBy::Dir, Path::new("./src/main.rs")), "src");
+        assert_eq!(key(GroupBy::Dir, Path::new("Cargo.toml")), ".");
+        assert_eq!(key(GroupBy::Dir, Path::new("/etc/passwd")), "/etc");
+        assert_eq!(key(GroupBy::Dir, Path::new("/vmlinuz")), "/");
+    }
+
+    #[test]
+    fn depth() {
+        assert_eq!(key(GroupBy::Depth(1), Path::new("src/counter/count.rs")), "src");
+        assert_eq!(key(GroupBy::Depth(2), Path::new("src/counter/count.rs")), "src/counter");
+        assert_eq!(key(GroupBy::Depth(3), Path::new("src/counter/count.rs")), "src/counter");
+        assert_eq!(key(GroupBy::Depth(1), Path::new("./src/main.rs")), "src");
+        assert_eq!(key(GroupBy::Depth(1), Path::new("Cargo.toml")), ".");
+        assert_eq!(key(GroupBy::Depth(1), Path::new("/usr/share/doc/file")), "/usr");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn latin1() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let file = Path::new(OsStr::from_bytes(b"caf\xE9/menu.txt"));
+        assert_eq!(key(GroupBy::Dir, file), OsStr::from_bytes(b"caf\xE9"));
+        assert_eq!(key(GroupBy::Ext, file), "txt");
+    }
+
+    #[test]
+    fn totals() {
+        let mut groups = Groups::new(GroupBy::Ext);
+        let opts = Options::test_empty().unwrap();
+        let count = |newlines, max_line| Count { newlines, words: 1, chars: 2, bytes: 3, max_line };
+        let metrics = |newlines, max_line| Metrics::from_count(&count(newlines, max_line), &opts);
+        groups.add("b.rs", &metrics(1, 4)).unwrap();
+        groups.add("a.md", &metrics(2, 5)).unwrap();
+        groups.add("c.rs", &metrics(3, 6)).unwrap();
+
+        let groups: Vec<_> = groups.iter().map(|(key, metrics)| (key.to_str().unwrap(), metrics.count())).collect();
+        assert_eq!(groups, vec![
+            ("md", count(2, 5)),
+            ("rs", Count { newlines: 4, words: 2, chars: 4, bytes: 6, max_line: 6 }),
+        ]);
+    }
+
+    #[test]
+    fn mismatch() {
+        let mut groups = Groups::new(GroupBy::Ext);
+        groups.add("a.rs", &Metrics::standard(&Options::test_args(vec!["-l"]).unwrap())).unwrap();
+        assert!(groups.add("b.rs", &Metrics::standard(&Options::test_args(vec!["-w"]).unwrap())).is_err());
+        assert!(groups.add("c.md", &Metrics::standard(&Options::test_args(vec!["-w"]).unwrap())).is_ok());
+    }
+}
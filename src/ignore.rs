@@ -0,0 +1,396 @@
+//! Ignore files (`.gitignore`, `.ignore`, and `.git/info/exclude`) for recursive counting.
+//!
+//! Patterns follow git's rules:
+//!
+//! - blank lines and lines starting with `#` are skipped
+//! - a leading `!` negates the pattern, including a file that an earlier pattern excluded
+//! - a trailing `/` only matches directories
+//! - a pattern with a `/` at the start or in the middle is anchored to the directory of the ignore
+//!   file, otherwise it matches at any depth below it
+//! - a later pattern overrides an earlier one, and the ignore files in deeper directories override
+//!   the ones above them
+//!
+//! Like git, `.gitignore` and `.git/info/exclude` are only used inside of a git repository, and a
+//! nested repository doesn't use the rules of the one it is in. `.ignore` is used everywhere.
+//!
+//! See the `glob` module for the pattern syntax.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use glob::Glob;
+
+/// The ignore files read in each directory, from highest to lowest precedence.
+pub const FILES: &[&str] = &[".ignore", ".gitignore"];
+
+/// The ignore file that is only read inside of a git repository.
+const GIT_FILE: &str = ".gitignore";
+
+#[derive(Debug)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The rules from a single ignore file.
+#[derive(Debug, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// Parse the contents of an ignore file.
+    pub fn parse(text: &str) -> Rules {
+        let rules = text.lines()
+            .filter_map(parse_line)
+            .collect();
+        Rules { rules }
+    }
+
+    /// Read and parse an ignore file. A missing file has no rules.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Rules> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Rules::parse(&String::from_utf8_lossy(&bytes))),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Rules::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check the given path, relative to the directory of the ignore file. Returns `Some(true)`
+    /// if the path is ignored, `Some(false)` if it was explicitly included with a `!` pattern, and
+    /// `None` if no pattern matched.
+    pub fn check(&self, path: &str, is_dir: bool) -> Option<bool> {
+        self.rules.iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.glob.is_match(path))
+            .map(|rule| !rule.negated)
+    }
+}
+
+fn parse_line(line: &str) -> Option<Rule> {
+    let line = line.trim_end_matches('\r');
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    // trailing spaces are removed unless they are escaped
+    let mut line = line.to_owned();
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line.pop();
+    }
+
+    let mut pattern = &line[..];
+    let negated = pattern.starts_with('!');
+    if negated {
+        pattern = &pattern[1..];
+    }
+
+    let dir_only = pattern.ends_with('/') && !pattern.ends_with("\\/");
+    if dir_only {
+        pattern = &pattern[..pattern.len() - 1];
+    }
+    if pattern.is_empty() {
+        return None;
+    }
+
+    // a `/` anywhere but the end anchors the pattern to the ignore file's directory
+    let anchored = pattern.contains('/');
+    let pattern = pattern.trim_start_matches('/');
+    let glob = if anchored { Glob::new(pattern) } else { Glob::new(pattern).in_any_dir() };
+
+    Some(Rule { glob, negated, dir_only })
+}
+
+/// The rules that apply to a directory, and the path the rules are relative to.
+#[derive(Debug)]
+struct Level {
+    /// Paths being checked are made relative to this directory.
+    dir: PathBuf,
+    /// Then this is added in front, for ignore files above the directory being walked.
+    prefix: String,
+    /// Rules from highest to lowest precedence.
+    rules: Vec<Rules>,
+    /// `true` if the directory is inside of a git repository.
+    in_repo: bool,
+    /// `true` if the directory is the root of a git repository, so the levels above it don't
+    /// apply.
+    repo_root: bool,
+}
+
+/// All of the ignore rules that apply to the directory currently being walked.
+#[derive(Debug, Default)]
+pub struct Stack {
+    levels: Vec<Level>,
+}
+
+impl Stack {
+    /// Create a stack for walking the given directory, with the rules from `.git/info/exclude`
+    /// and the ignore files in the directories above it, up to the root of its git repository.
+    pub fn new(dir: &Path) -> io::Result<Stack> {
+        let mut stack = Stack::default();
+        let absolute = match fs::canonicalize(dir) {
+            Ok(absolute) => absolute,
+            Err(_) => return Ok(stack),
+        };
+
+        let root = match absolute.ancestors().find(|a| a.join(".git").exists()) {
+            Some(root) => root,
+            None => return Ok(stack),
+        };
+
+        // `.git/info/exclude` has the lowest precedence, then the directories from the root down
+        // to (but not including) the one being walked
+        let exclude = Rules::from_file(root.join(".git/info/exclude"))?;
+        stack.levels.push(Level {
+            dir: dir.to_owned(),
+            prefix: relative(&absolute, root),
+            rules: vec![exclude],
+            in_repo: true,
+            repo_root: true,
+        });
+
+        let parents: Vec<_> = absolute.ancestors().skip(1).take_while(|a| a.starts_with(root)).collect();
+        for parent in parents.into_iter().rev() {
+            let mut rules = Vec::new();
+            for file in FILES {
+                rules.push(Rules::from_file(parent.join(file))?);
+            }
+            stack.levels.push(Level {
+                dir: dir.to_owned(),
+                prefix: relative(&absolute, parent),
+                rules,
+                in_repo: true,
+                repo_root: false,
+            });
+        }
+
+        Ok(stack)
+    }
+
+    /// Add the rules from the ignore files in the given directory. A level is always added so it
+    /// can be removed with `pop()`, even if one of the files couldn't be read.
+    ///
+    /// If the directory is the root of a git repository, its `.git/info/exclude` is read too, and
+    /// the rules from the directories above it stop applying until it is popped.
+    pub fn push(&mut self, dir: &Path) -> io::Result<()> {
+        let repo_root = dir.join(".git").exists();
+        let in_repo = repo_root || self.levels.last().is_some_and(|level| level.in_repo);
+
+        let mut files: Vec<PathBuf> = FILES.iter()
+            .filter(|file| in_repo || **file != GIT_FILE)
+            .map(|file| dir.join(file))
+            .collect();
+        if repo_root {
+            files.push(dir.join(".git/info/exclude"));
+        }
+
+        let mut rules = Vec::new();
+        let mut result = Ok(());
+        for file in files {
+            match Rules::from_file(file) {
+                Ok(file_rules) => rules.push(file_rules),
+                Err(e) => result = Err(e),
+            }
+        }
+        self.levels.push(Level { dir: dir.to_owned(), prefix: String::new(), rules, in_repo, repo_root });
+        result
+    }
+
+    /// Remove the rules added by the last call to `push()`.
+    pub fn pop(&mut self) {
+        self.levels.pop();
+    }
+
+    /// Return `true` if the given path (inside of the directory being walked) is ignored.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for level in self.levels.iter().rev() {
+            let mut relative = relative(path, &level.dir);
+            if !level.prefix.is_empty() {
+                relative = format!("{}/{}", level.prefix, relative);
+            }
+            for rules in &level.rules {
+                if let Some(ignored) = rules.check(&relative, is_dir) {
+                    return ignored;
+                }
+            }
+            if level.repo_root {
+                break;
+            }
+        }
+        false
+    }
+}
+
+/// Return `path` relative to `base` with `/` separators.
+fn relative(path: &Path, base: &Path) -> String {
+    let path = path.strip_prefix(base).unwrap_or(path);
+    let parts: Vec<_> = path.components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rules, Stack};
+    use std::fs;
+    use std::path::Path;
+    use test_util::Tree;
+
+    #[test]
+    fn comments_and_blanks() {
+        let rules = Rules::parse("# comment\n\n   \n\\#not-a-comment\n");
+        assert_eq!(rules.check("#not-a-comment", false), Some(true));
+        assert_eq!(rules.check("# comment", false), None);
+    }
+
+    #[test]
+    fn unanchored() {
+        let rules = Rules::parse("*.log\ntarget\n");
+        assert_eq!(rules.check("a.log", false), Some(true));
+        assert_eq!(rules.check("deep/down/a.log", false), Some(true));
+        assert_eq!(rules.check("target", true), Some(true));
+        assert_eq!(rules.check("sub/target", true), Some(true));
+        assert_eq!(rules.check("a.rs", false), None);
+    }
+
+    #[test]
+    fn anchored() {
+        let rules = Rules::parse("/target\ndoc/*.html\n");
+        assert_eq!(rules.check("target", true), Some(true));
+        assert_eq!(rules.check("sub/target", true), None);
+        assert_eq!(rules.check("doc/index.html", false), Some(true));
+        assert_eq!(rules.check("sub/doc/index.html", false), None);
+        assert_eq!(rules.check("doc/api/index.html", false), None);
+    }
+
+    #[test]
+    fn dir_only() {
+        let rules = Rules::parse("build/\n");
+        assert_eq!(rules.check("build", true), Some(true));
+        assert_eq!(rules.check("build", false), None);
+        assert_eq!(rules.check("src/build", true), Some(true));
+    }
+
+    #[test]
+    fn negation() {
+        let rules = Rules::parse("*.log\n!keep.log\n");
+        assert_eq!(rules.check("a.log", false), Some(true));
+        assert_eq!(rules.check("keep.log", false), Some(false));
+
+        // the last match wins
+        let rules = Rules::parse("!keep.log\n*.log\n");
+        assert_eq!(rules.check("keep.log", false), Some(true));
+    }
+
+    #[test]
+    fn trailing_spaces() {
+        let rules = Rules::parse("a.txt   \nb\\ \n");
+        assert_eq!(rules.check("a.txt", false), Some(true));
+        assert_eq!(rules.check("b ", false), Some(true));
+    }
+
+    #[test]
+    fn double_star() {
+        let rules = Rules::parse("**/gen/**\nfoo/**/bar\n");
+        assert_eq!(rules.check("gen/a.rs", false), Some(true));
+        assert_eq!(rules.check("x/gen/y/a.rs", false), Some(true));
+        assert_eq!(rules.check("foo/bar", false), Some(true));
+        assert_eq!(rules.check("foo/a/b/bar", false), Some(true));
+    }
+
+    #[test]
+    fn stack() {
+        let tree = Tree::new("ignore-stack", &[".git/info/exclude", "sub/.gitignore", "sub/.ignore", ".gitignore"]);
+        fs::write(tree.path(".git/info/exclude"), "*.exclude\n").unwrap();
+        fs::write(tree.path(".gitignore"), "*.log\n/top\n").unwrap();
+        fs::write(tree.path("sub/.gitignore"), "!keep.log\n*.tmp\n").unwrap();
+        fs::write(tree.path("sub/.ignore"), "!keep.tmp\n").unwrap();
+
+        // walking from the root of the repository
+        let root = Path::new(&tree.root);
+        let mut stack = Stack::new(root).unwrap();
+        stack.push(root).unwrap();
+        assert!(stack.is_ignored(&root.join("a.exclude"), false));
+        assert!(stack.is_ignored(&root.join("a.log"), false));
+        assert!(stack.is_ignored(&root.join("top"), false));
+        assert!(!stack.is_ignored(&root.join("a.rs"), false));
+
+        let sub = root.join("sub");
+        stack.push(&sub).unwrap();
+        assert!(stack.is_ignored(&sub.join("a.log"), false));
+        assert!(!stack.is_ignored(&sub.join("keep.log"), false));
+        assert!(stack.is_ignored(&sub.join("a.tmp"), false));
+        assert!(!stack.is_ignored(&sub.join("keep.tmp"), false));
+        assert!(!stack.is_ignored(&sub.join("top"), false));
+        stack.pop();
+        stack.pop();
+
+        // walking from a subdirectory still uses the rules above it
+        let mut stack = Stack::new(&sub).unwrap();
+        stack.push(&sub).unwrap();
+        assert!(stack.is_ignored(&sub.join("a.exclude"), false));
+        assert!(stack.is_ignored(&sub.join("a.log"), false));
+        assert!(!stack.is_ignored(&sub.join("keep.log"), false));
+        assert!(!stack.is_ignored(&sub.join("top"), false));
+    }
+
+    /// `.gitignore` is only read inside of a repository, `.ignore` is read everywhere.
+    #[test]
+    fn outside_repo() {
+        let tree = Tree::new("ignore-outside-repo", &[".gitignore", ".ignore"]);
+        fs::write(tree.path(".gitignore"), "*.log
+").unwrap();
+        fs::write(tree.path(".ignore"), "*.tmp
+").unwrap();
+
+        let root = Path::new(&tree.root);
+        let mut stack = Stack::new(root).unwrap();
+        stack.push(root).unwrap();
+        assert!(!stack.is_ignored(&root.join("a.log"), false));
+        assert!(stack.is_ignored(&root.join("a.tmp"), false));
+    }
+
+    /// A nested repository has its own rules, not the ones of the repository it is in.
+    #[test]
+    fn nested_repo() {
+        let tree = Tree::new("ignore-nested-repo", &[".git/info/exclude", ".gitignore",
+                                                     "inner/.git/info/exclude", "inner/.gitignore"]);
+        fs::write(tree.path(".git/info/exclude"), "*.exclude
+").unwrap();
+        fs::write(tree.path(".gitignore"), "*.log
+").unwrap();
+        fs::write(tree.path("inner/.git/info/exclude"), "*.inner
+").unwrap();
+        fs::write(tree.path("inner/.gitignore"), "*.tmp
+").unwrap();
+
+        let root = Path::new(&tree.root);
+        let inner = root.join("inner");
+        let mut stack = Stack::new(root).unwrap();
+        stack.push(root).unwrap();
+        stack.push(&inner).unwrap();
+        assert!(!stack.is_ignored(&inner.join("a.exclude"), false));
+        assert!(!stack.is_ignored(&inner.join("a.log"), false));
+        assert!(stack.is_ignored(&inner.join("a.inner"), false));
+        assert!(stack.is_ignored(&inner.join("a.tmp"), false));
+
+        // the outer rules apply again after leaving the nested repository
+        stack.pop();
+        assert!(stack.is_ignored(&root.join("a.log"), false));
+        assert!(!stack.is_ignored(&root.join("a.inner"), false));
+
+        // and walking from inside of the nested repository only uses its rules
+        let sub = inner.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        let mut stack = Stack::new(&sub).unwrap();
+        stack.push(&sub).unwrap();
+        assert!(!stack.is_ignored(&sub.join("a.log"), false));
+        assert!(stack.is_ignored(&sub.join("a.inner"), false));
+        assert!(stack.is_ignored(&sub.join("a.tmp"), false));
+    }
+}
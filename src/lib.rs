@@ -1,2 +1,12 @@
 pub mod counter;
+pub mod filter;
+pub mod glob;
+pub mod group;
+pub mod ignore;
 pub mod options;
+pub mod pool;
+pub mod quote;
+pub mod walk;
+
+#[cfg(test)]
+mod test_util;
@@ -1,15 +1,28 @@
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::Path;
 use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::io::prelude::*;
 use std::io::BufWriter;
 use std::io::{stderr, stdout};
-use std::error::Error;
 extern crate rust_wc;
-use rust_wc::counter::Count;
+use rust_wc::counter;
+use rust_wc::counter::{is_archive, plan_width, Count, MemberResult, Metrics, Delimited, Json, Separator, Template};
+use rust_wc::group::Groups;
 use rust_wc::options;
-use rust_wc::options::Options;
+use rust_wc::options::{Format, OnError, Options};
+use rust_wc::pool;
+use rust_wc::pool::Budget;
+use rust_wc::quote::{escape, quote, quote_bytes};
+use rust_wc::walk;
+
+/// The program name at the start of error messages, the same as GNU wc.
+const PROGRAM: &str = "wc";
 
 fn main() {
-    let opts = match Options::new() {
+    let mut opts = match Options::new() {
         Ok(opts) => opts,
         Err(e @ options::Error::Usage) => {
             println!("{}", e);
@@ -25,45 +38,380 @@ fn main() {
         }
     };
 
+    let mut failures = 0;
+    let mut aborted = false;
+    if opts.recursive {
+        let walked = walk::expand(&opts.files, &opts);
+        for e in walked.errors {
+            report(&e.path, &counter::Error::from(e.error));
+            failures += 1;
+            if opts.on_error == Some(OnError::Abort) {
+                aborted = true;
+                break;
+            }
+        }
+        opts.files = if aborted { Vec::new() } else { walked.files };
+    }
+    else if !opts.filter.is_empty() {
+        let filter = &opts.filter;
+        opts.files.retain(|file| file == Path::new("-") || filter.is_match(&file.to_string_lossy()));
+    }
+
+    // every input is counted for the same set of metrics, so they can all be merged into the total
+    let metrics = Metrics::standard(&opts);
     let stdout = stdout();
     let stdout_lock = stdout.lock();
-    let mut out = BufWriter::new(stdout_lock);
+    let mut printer = Printer::new(BufWriter::new(stdout_lock), &opts, &metrics);
+
+    printer.start();
+    let mut total = metrics.empty();
+    // with --on-error=abort, the files after the first failure aren't counted or printed, but the
+    // groups, the total, and the summary still are
+    let aborted = AtomicBool::new(aborted);
+    // the files and the chunks of large files are counted with the same jobs
+    let budget = Budget::new(opts.jobs);
+    pool::ordered(&opts.files, &budget, |file| {
+        if aborted.load(Ordering::Relaxed) { None } else { Some(process(&opts, &metrics, file, &budget)) }
+    }, |file, counted| {
+        let counted = match counted {
+            Some(counted) if !aborted.load(Ordering::Relaxed) => counted,
+            _ => return,
+        };
+        let printed = match counted {
+            Counted::File(result) => {
+                if let Some(counts) = counts(&opts, &metrics, result.as_ref()) {
+                    merge(&mut total, &counts);
+                }
+                printer.print(file, &result)
+            }
+            Counted::Archive(Ok(members)) => {
+                // a member that can't be counted is reported like a file, the others still are
+                let mut subtotal = metrics.empty();
+                let mut printed = Ok(());
+                for member in members {
+                    let (label, result) = match member {
+                        Ok(member) => (member.label(file), Ok(member.count)),
+                        Err(e) => (e.path().to_owned(), Err(e)),
+                    };
+                    if let Some(counts) = counts(&opts, &metrics, result.as_ref()) {
+                        merge(&mut subtotal, &counts);
+                    }
+                    printed = printer.print(&label, &result);
+                    if printed.is_err() {
+                        break;
+                    }
+                }
+                printer.subtotal(file, &subtotal);
+                merge(&mut total, &subtotal);
+                printed
+            }
+            Counted::Archive(Err(e)) => printer.print(file, &Err(e)),
+        };
+        if printed.is_err() {
+            aborted.store(true, Ordering::Relaxed);
+        }
+    });
 
-    let mut total = Count::new();
-    for file in opts.files.iter() {
-        let result = process_file(&opts, file);
-        print_count(&mut out, &opts, file, &result);
-        if let Ok(count) = result {
-            total = total + count;
+    // no files provided, read from stdin
+    if opts.read_stdin {
+        let stdin = Path::new("-");
+        let result = process_file(&opts, &metrics, stdin, &budget);
+        if let Some(counts) = counts(&opts, &metrics, result.as_ref()) {
+            merge(&mut total, &counts);
         }
+        // there is nothing left to skip after stdin
+        let _ = printer.print(stdin, &result);
     }
+    printer.finish(&total);
+
+    // like GNU wc, exit with an error if any file couldn't be counted
+    failures += printer.failures;
+    drop(printer);
+    if failures > 0 {
+        // GNU wc has no summary, so it is only printed when an error policy is chosen
+        if opts.on_error.is_some() {
+            let files = if failures == 1 { "file" } else { "files" };
+            writeln!(stderr(), "{}: {} {} couldn't be counted", PROGRAM, failures, files).expect("error writing to stderr");
+        }
+        process::exit(1);
+    }
+}
+
+/// Print an error message for a file the way GNU wc does, with the name quoted if it needs to be.
+fn report<P: AsRef<OsStr>>(file: P, error: &dyn fmt::Display) {
+    writeln!(stderr(), "{}: {}: {}", PROGRAM, escape(file.as_ref()), error).expect("error writing to stderr");
+}
 
-    match opts.files.len() {
-        // no files provided, read from stdin
-        0 => print_count(&mut out, &opts, "-", &Count::from_stdin()),
-        // print the total count if more than one file was provided
-        c if c > 1 => { writeln!(out, "{} total", total.display(&opts)).unwrap(); }
-        // else do nothing
-        _ => {}
+/// The counts to print and add to the total for a file, for the set of metrics every file is
+/// counted for. For errors, this depends on the `--on-error` policy, and by default is zeros or
+/// the counts from before the error, like GNU wc.
+fn counts<'b>(opts: &Options, metrics: &Metrics, count_result: Result<&'b Metrics, &counter::Error>) -> Option<Cow<'b, Metrics>> {
+    match count_result {
+        Ok(counted) => Some(Cow::Borrowed(counted)),
+        Err(e) => match opts.on_error {
+            None => e.counts().map(|count| Cow::Owned(metrics.with_count(&count))),
+            Some(OnError::Zero) => Some(Cow::Owned(metrics.empty())),
+            Some(OnError::Skip) | Some(OnError::Abort) => None,
+        },
     }
 }
 
-fn print_count(out: &mut Write, opts: &Options, file: &str, count_result: &Result<Count, Box<Error>>) {
-    match *count_result {
-        Err(ref e) => {
-            out.flush().unwrap();
-            writeln!(stderr(), "{}: {}", file, e).expect("error writing to stderr");
-            writeln!(*out, "{} {}", Count::new().display(&opts), file).unwrap();
+/// Add the counts for a file to a total. Both are for the same set of metrics, since every file
+/// is counted for the one from `main()`.
+fn merge(total: &mut Metrics, counts: &Metrics) {
+    total.merge(counts).expect("files were counted for different metrics");
+}
+
+type CountResult = Result<Metrics, counter::Error>;
+
+/// Returned by `Printer::print()` when a file couldn't be counted and `--on-error=abort` says to
+/// stop.
+#[derive(Debug)]
+struct Abort;
+
+/// The counts for an input, or with `--archive`, for each file inside of it.
+enum Counted {
+    File(CountResult),
+    Archive(Result<Vec<MemberResult<Metrics>>, counter::Error>),
+}
+
+/// Prints the counts for each file in the output format selected in the options.
+struct Printer<'a, W: Write> {
+    out: W,
+    opts: &'a Options,
+    /// The set of metrics every file is counted for, with nothing counted.
+    metrics: &'a Metrics,
+    /// The number of files printed so far.
+    printed: usize,
+    /// The field width for text output.
+    width: usize,
+    /// The totals for each group with `--group-by`, printed before the total.
+    groups: Option<Groups>,
+    /// The number of files that couldn't be counted.
+    failures: usize,
+    /// The JSON records for the archive subtotals, printed after the files.
+    subtotals: Vec<String>,
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    fn new(out: W, opts: &'a Options, metrics: &'a Metrics) -> Self {
+        Printer {
+            out,
+            opts,
+            metrics,
+            printed: 0,
+            // there is nothing to line up with when only the total is printed
+            width: if opts.print_files() { plan_width(&opts.files, opts) } else { 1 },
+            groups: opts.group_by.map(Groups::new),
+            failures: 0,
+            subtotals: Vec::new(),
         }
-        Ok(ref count) => {
-            writeln!(*out, "{} {}", count.display(&opts), file).unwrap();
+    }
+
+    /// Print anything that comes before the counts for the first file.
+    fn start(&mut self) {
+        match self.opts.format {
+            Format::Text | Format::Ndjson | Format::Printf => {}
+            Format::Json => if self.opts.print_files() {
+                let key = if self.opts.group_by.is_some() { "groups" } else { "files" };
+                write!(self.out, "{{\"{}\":[", key).unwrap();
+            }
+            else {
+                write!(self.out, "{{").unwrap();
+            },
+            Format::Csv => { writeln!(self.out, "{}", Delimited::header(Separator::Comma, self.metrics, self.opts)).unwrap(); }
+            Format::Tsv => { writeln!(self.out, "{}", Delimited::header(Separator::Tab, self.metrics, self.opts)).unwrap(); }
         }
     }
+
+    /// Print the counts for a single file, or the error if it couldn't be counted. When grouping,
+    /// the counts are added to the file's group instead.
+    ///
+    /// With `--on-error=abort`, an error is reported without a row and `Abort` is returned, so
+    /// the caller can stop counting files.
+    fn print(&mut self, file: &Path, count_result: &CountResult) -> Result<(), Abort> {
+        if let Err(ref e) = *count_result {
+            self.out.flush().unwrap();
+            // GNU wc names stdin when it is read because no files were given
+            if file == Path::new("-") && self.opts.read_stdin { report("standard input", e); }
+            else { report(file, e); }
+            self.failures += 1;
+            if self.opts.on_error == Some(OnError::Abort) {
+                return Err(Abort);
+            }
+        }
+        if let Some(ref mut groups) = self.groups {
+            if let Some(counts) = counts(self.opts, self.metrics, count_result.as_ref()) {
+                groups.add(file, &counts).expect("files were counted for different metrics");
+            }
+            return Ok(());
+        }
+        // text output has no row for a file without counts, the other formats have the error
+        let skip = count_result.is_err() && match self.opts.on_error {
+            Some(OnError::Skip) => true,
+            _ => self.opts.format == Format::Text && counts(self.opts, self.metrics, count_result.as_ref()).is_none(),
+        };
+        if self.opts.print_files() && !skip {
+            self.row(file, count_result.as_ref());
+        }
+        Ok(())
+    }
+
+    /// Print the subtotal for the files inside of an archive. The formats meant for other programs
+    /// tell it apart from the files, so it isn't counted twice by adding up the file records.
+    fn subtotal(&mut self, archive: &Path, subtotal: &Metrics) {
+        if self.groups.is_some() || !self.opts.print_files() {
+            return;
+        }
+        let name = quote(archive.as_os_str(), self.opts.quoting);
+        match self.opts.format {
+            Format::Text | Format::Printf => self.row(archive, Ok(subtotal)),
+            // the subtotals get their own list, after the files
+            Format::Json => self.subtotals.push(Json::new(self.opts).archive(&name).metrics(subtotal).to_string()),
+            Format::Ndjson => {
+                let json = Json::new(self.opts).record_type("subtotal").archive(&name).metrics(subtotal);
+                writeln!(self.out, "{}", json).unwrap();
+                self.out.flush().unwrap();
+            }
+            Format::Csv | Format::Tsv => {
+                let label = format!("{} (subtotal)", name);
+                let row = Delimited::new(self.separator(), &label, self.opts).metrics(subtotal);
+                writeln!(self.out, "{}", row).unwrap();
+            }
+        }
+    }
+
+    /// Print a row for a file or a group.
+    fn row(&mut self, file: &Path, count_result: Result<&Metrics, &counter::Error>) {
+        let name = quote(file.as_os_str(), self.opts.quoting);
+        match self.opts.format {
+            Format::Text => {
+                let counts = counts(self.opts, self.metrics, count_result).unwrap_or_else(|| Cow::Owned(self.metrics.empty()));
+                let display = counts.display(self.opts).width(self.width);
+                // like GNU wc, there is no name when reading stdin because no files were given
+                if self.opts.read_stdin && self.opts.group_by.is_none() { writeln!(self.out, "{}", display).unwrap(); }
+                else {
+                    write!(self.out, "{} ", display).unwrap();
+                    self.out.write_all(&quote_bytes(file.as_os_str(), self.opts.quoting)).unwrap();
+                    writeln!(self.out).unwrap();
+                }
+            }
+            Format::Json => {
+                let separator = if self.printed > 0 { "," } else { "" };
+                let json = self.json(&name);
+                match count_result {
+                    Ok(counted) => write!(self.out, "{}\n{}", separator, json.metrics(counted)).unwrap(),
+                    Err(e) => write!(self.out, "{}\n{}", separator, json.error(e)).unwrap(),
+                }
+            }
+            Format::Ndjson => {
+                // each record is flushed so it can be consumed while we are still counting
+                let record_type = if self.opts.group_by.is_some() { "group" } else { "file" };
+                let json = self.json(&name).record_type(record_type);
+                match count_result {
+                    Ok(counted) => writeln!(self.out, "{}", json.metrics(counted)).unwrap(),
+                    Err(e) => writeln!(self.out, "{}", json.error(e)).unwrap(),
+                }
+                self.out.flush().unwrap();
+            }
+            Format::Csv | Format::Tsv => {
+                let row = Delimited::new(self.separator(), &name, self.opts);
+                match count_result {
+                    Ok(counted) => writeln!(self.out, "{}", row.metrics(counted)).unwrap(),
+                    Err(e) => writeln!(self.out, "{}", row.metrics(self.metrics).error(e)).unwrap(),
+                }
+            }
+            Format::Printf => {
+                let template = self.template();
+                match count_result {
+                    Ok(counted) => write!(self.out, "{}", template.render(&name, Some(counted), None)).unwrap(),
+                    Err(e) => write!(self.out, "{}", template.render(&name, None, Some(e))).unwrap(),
+                }
+            }
+        }
+        self.printed += 1;
+    }
+
+    /// Print the groups, the total, and anything that comes after them.
+    fn finish(&mut self, total: &Metrics) {
+        if let Some(groups) = self.groups.take() {
+            if self.opts.print_files() {
+                for (key, counts) in groups.iter() {
+                    self.row(Path::new(key), Ok(counts));
+                }
+            }
+        }
+
+        let print_total = self.opts.print_total();
+        match self.opts.format {
+            Format::Text => if print_total {
+                let display = total.display(self.opts).width(self.width);
+                // like GNU wc, the total isn't labeled when it is the only thing printed
+                if self.opts.print_files() { writeln!(self.out, "{} total", display).unwrap(); }
+                else { writeln!(self.out, "{}", display).unwrap(); }
+            },
+            Format::Json => {
+                let mut separator = "";
+                if self.opts.print_files() {
+                    write!(self.out, "\n]").unwrap();
+                    separator = ",";
+                }
+                if !self.subtotals.is_empty() {
+                    write!(self.out, "{}\"subtotals\":[\n{}\n]", separator, self.subtotals.join(",\n")).unwrap();
+                    separator = ",";
+                }
+                if print_total {
+                    write!(self.out, "{}\"total\":{}", separator, total.json(self.opts)).unwrap();
+                }
+                writeln!(self.out, "}}").unwrap();
+            }
+            Format::Ndjson => if print_total {
+                writeln!(self.out, "{}", total.json(self.opts).record_type("total")).unwrap();
+            },
+            Format::Csv | Format::Tsv => if print_total {
+                let row = Delimited::new(self.separator(), "total", self.opts);
+                writeln!(self.out, "{}", row.metrics(total)).unwrap();
+            },
+            Format::Printf => if print_total {
+                write!(self.out, "{}", self.template().render("total", Some(total), None)).unwrap();
+            },
+        }
+    }
+
+    fn json<'b>(&self, name: &'b str) -> Json<'b> where 'a: 'b {
+        let json = Json::new(self.opts);
+        if self.opts.group_by.is_some() { json.group(name) } else { json.file(name) }
+    }
+
+    fn template(&self) -> &'a Template {
+        self.opts.printf.as_ref().expect("--printf format is missing")
+    }
+
+    fn separator(&self) -> Separator {
+        match self.opts.format {
+            Format::Tsv => Separator::Tab,
+            _ => Separator::Comma,
+        }
+    }
+}
+
+fn process(opts: &Options, metrics: &Metrics, file: &Path, budget: &Budget) -> Counted {
+    if opts.archive && file != Path::new("-") && is_archive(file) { Counted::Archive(metrics.count_archive(file)) }
+    else { Counted::File(process_file(opts, metrics, file, budget)) }
 }
 
-fn process_file(opts: &Options, file: &str) -> Result<Count, Box<Error>> {
-    if file == "-" { Count::from_stdin() }
-    else if opts.only_bytes() { Count::bytes_from_file(file) }
-    else if opts.only_lines() { Count::lines_from_file(file) }
-    else { Count::from_file(file) }
+fn process_file(opts: &Options, metrics: &Metrics, file: &Path, budget: &Budget) -> CountResult {
+    let stdin = file == Path::new("-");
+    if opts.decompress {
+        if stdin { metrics.count_stdin_decompressed() }
+        else { metrics.count_file_decompressed(file) }
+    }
+    else if stdin { metrics.count_stdin() }
+    // bytes and lines can be counted without looking at every byte, unless another metric has to
+    else if metrics.is_standard() && opts.only_bytes() { Count::bytes_from_file(file).map(|count| metrics.with_count(&count)) }
+    // large files are split across whatever jobs aren't busy counting other files
+    else if metrics.is_standard() && opts.only_lines() {
+        Count::lines_from_mmap_chunked(file, budget).map(|count| metrics.with_count(&count))
+    }
+    else { metrics.count_file(file, budget) }
 }
@@ -1,17 +1,31 @@
 extern crate getopts;
 use std::env;
 use std::result;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::io;
 use std::io::prelude::*;
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str;
 use std::string;
+use pool;
+use counter::{metadata, Bytes, Chars, Lines, MaxLineLength, Template, Words};
+use filter::Filter;
 
 #[derive(Debug)]
 pub enum Error {
     Usage,
     Version,
     Files0FromWithFiles,
+    Jobs(String),
+    Format(String),
+    Printf(String),
+    PrintfWithFormat,
+    Total(String),
+    MaxDepth(String),
+    GroupBy(String),
+    OnError(String),
+    Quoting(String),
     Getopts(getopts::Fail),
     Io(io::Error),
     Utf8(string::FromUtf8Error),
@@ -24,6 +38,15 @@ impl fmt::Display for Error {
             Error::Usage => write!(f, "{}", Options::usage()),
             Error::Version => write!(f, "{}", Options::version()),
             Error::Files0FromWithFiles => write!(f, "invalid arguments: can't use --files0-from with a FILEs list"),
+            Error::Jobs(ref n) => write!(f, "invalid arguments: invalid number of jobs: '{}'", n),
+            Error::Format(ref format) => write!(f, "invalid arguments: unknown format: '{}'", format),
+            Error::Printf(ref e) => write!(f, "invalid arguments: invalid --printf format: {}", e),
+            Error::PrintfWithFormat => write!(f, "invalid arguments: can't use --printf with --format"),
+            Error::Total(ref when) => write!(f, "invalid arguments: invalid --total mode: '{}'", when),
+            Error::MaxDepth(ref n) => write!(f, "invalid arguments: invalid maximum depth: '{}'", n),
+            Error::GroupBy(ref by) => write!(f, "invalid arguments: invalid --group-by key: '{}'", by),
+            Error::OnError(ref policy) => write!(f, "invalid arguments: invalid --on-error policy: '{}'", policy),
+            Error::Quoting(ref style) => write!(f, "invalid arguments: invalid --quoting style: '{}'", style),
             Error::Getopts(ref e) => write!(f, "invalid arguments: {}", e),
             Error::Io(ref e) => write!(f, "error reading file list: {}", e),
             Error::Utf8(ref e) => write!(f, "error reading file list, invalid utf8: {}", e),
@@ -52,13 +75,159 @@ impl From<string::FromUtf8Error> for Error {
 
 pub type Result = result::Result<Options, Error>;
 
+/// When to print the total.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Total {
+    /// Print the total if there is more than one file or files are grouped, or always for JSON
+    /// output.
+    Auto,
+    /// Always print the total.
+    Always,
+    /// Only print the total, not the counts for each file.
+    Only,
+    /// Never print the total.
+    Never,
+}
+
+impl Total {
+    fn from_str(when: &str) -> result::Result<Total, Error> {
+        match when {
+            "auto" => Ok(Total::Auto),
+            "always" => Ok(Total::Always),
+            "only" => Ok(Total::Only),
+            "never" => Ok(Total::Never),
+            _ => Err(Error::Total(when.to_owned())),
+        }
+    }
+}
+
+/// How to group files with `--group-by`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GroupBy {
+    /// The file extension.
+    Ext,
+    /// The directory the file is in.
+    Dir,
+    /// The first N directories of the path.
+    Depth(usize),
+}
+
+impl GroupBy {
+    fn from_str(by: &str) -> result::Result<GroupBy, Error> {
+        match by {
+            "ext" => Ok(GroupBy::Ext),
+            "dir" => Ok(GroupBy::Dir),
+            _ => match by.strip_prefix("depth=").map(str::parse) {
+                Some(Ok(depth)) if depth > 0 => Ok(GroupBy::Depth(depth)),
+                _ => Err(Error::GroupBy(by.to_owned())),
+            },
+        }
+    }
+}
+
+/// What to do when a file can't be counted, with `--on-error`. Without it, errors are handled
+/// like GNU wc: directories and failed reads get a row, files that can't be opened don't.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnError {
+    /// Don't print a row for the file or add it to the total.
+    Skip,
+    /// Print a row of zeros for the file.
+    Zero,
+    /// Stop counting and exit.
+    Abort,
+}
+
+impl OnError {
+    fn from_str(policy: &str) -> result::Result<OnError, Error> {
+        match policy {
+            "skip" => Ok(OnError::Skip),
+            "zero" => Ok(OnError::Zero),
+            "abort" => Ok(OnError::Abort),
+            _ => Err(Error::OnError(policy.to_owned())),
+        }
+    }
+}
+
+/// How file names are printed, with `--quoting`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quoting {
+    /// Print names as they are, like GNU wc. Names with a newline are escaped, and output that
+    /// has to be UTF-8 (everything but text) gets the lossy conversion.
+    Literal,
+    /// Replace anything that isn't valid UTF-8 with U+FFFD.
+    Lossy,
+    /// Quote and escape names the way a shell would need them, see `quote::escape()`.
+    Escape,
+}
+
+impl Quoting {
+    fn from_str(style: &str) -> result::Result<Quoting, Error> {
+        match style {
+            "literal" => Ok(Quoting::Literal),
+            "lossy" => Ok(Quoting::Lossy),
+            "escape" => Ok(Quoting::Escape),
+            _ => Err(Error::Quoting(style.to_owned())),
+        }
+    }
+}
+
+/// The output format for the counts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Counts in aligned columns followed by the file name.
+    Text,
+    /// A JSON document with an object for each file and the total.
+    Json,
+    /// A JSON object per line for each file, followed by one for the total.
+    Ndjson,
+    /// Comma separated values with a header row.
+    Csv,
+    /// Tab separated values with a header row.
+    Tsv,
+    /// A user defined format for each file and the total, see `counter::Template`.
+    Printf,
+}
+
+impl Format {
+    fn from_str(format: &str) -> result::Result<Format, Error> {
+        match format {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            _ => Err(Error::Format(format.to_owned())),
+        }
+    }
+}
+
 pub struct Options {
-    pub files: Vec<String>,
+    pub files: Vec<PathBuf>,
     pub bytes: bool,
     pub chars: bool,
     pub lines: bool,
     pub max_line: bool,
     pub words: bool,
+    pub jobs: usize,
+    pub format: Format,
+    pub printf: Option<Template>,
+    pub total: Total,
+    pub recursive: bool,
+    pub follow_symlinks: bool,
+    pub max_depth: Option<usize>,
+    pub skip_hidden: bool,
+    pub no_ignore: bool,
+    pub filter: Filter,
+    pub group_by: Option<GroupBy>,
+    pub on_error: Option<OnError>,
+    pub quoting: Quoting,
+    pub decompress: bool,
+    pub archive: bool,
+    /// `true` if no files were given, so stdin is counted.
+    pub read_stdin: bool,
+    /// `true` if the `--files0-from` list isn't a regular file, like a pipe. GNU wc reads the
+    /// names from those one at a time, so it doesn't look at the files before counting them.
+    pub files0_streamed: bool,
 }
 
 impl Options {
@@ -107,7 +276,7 @@ Ditributed under the MIT license: https://opensource.org/licenses/MIT"
 
     #[cfg(test)]
     pub fn test_empty() -> Result {
-        Self::from_iter(vec!["test"].iter())
+        Self::from_iter(["test"].iter())
     }
 
     fn options() -> getopts::Options {
@@ -117,6 +286,22 @@ Ditributed under the MIT license: https://opensource.org/licenses/MIT"
         opts.optflag("l", "lines", "print the newline counts");
         opts.optflag("L", "max-line-length", "print the length of the longest line");
         opts.optflag("w", "words", "print the word counts");
+        opts.optopt("j", "jobs", "count with up to N jobs in parallel, spread over the files and the chunks of large files; defaults to the number of CPUs", "N");
+        opts.optopt("", "format", "print the counts as text (the default), json, ndjson, csv, or tsv", "FORMAT");
+        opts.optopt("", "printf", "print the counts using FORMAT, where %l, %w, %m, %c, and %L are the newline, word, character, byte, and maximum line length counts, %n is the file name, and %e is the error message", "FORMAT");
+        opts.optopt("", "total", "when to print a line with total counts; WHEN can be: auto, always, only, never", "WHEN");
+        opts.optflag("r", "recursive", "count every regular file in the directories given, and their subdirectories; with no FILEs, count the current directory");
+        opts.optflag("", "follow-symlinks", "follow symbolic links when counting recursively");
+        opts.optopt("", "max-depth", "descend at most N levels below the directories given when counting recursively", "N");
+        opts.optflag("", "skip-hidden", "skip files and directories starting with a '.' when counting recursively");
+        opts.optmulti("", "include", "only count files matching the glob PATTERN; patterns without a '/' match the file name", "PATTERN");
+        opts.optmulti("", "exclude", "don't count files matching the glob PATTERN, and don't descend into directories matching it", "PATTERN");
+        opts.optflag("", "decompress", "count the decompressed contents of gzip, bzip2, xz, and zstd files");
+        opts.optflag("", "archive", "count each file inside of .tar, .tar.gz, and .zip archives, followed by a subtotal for the archive");
+        opts.optopt("", "group-by", "print the counts for groups of files instead of each file; KEY can be: ext, dir, depth=N", "KEY");
+        opts.optopt("", "on-error", "what to do when a file can't be counted; POLICY can be: skip (print no row), zero (print a row of zeros), abort (stop and exit); a summary of the failures is printed at the end", "POLICY");
+        opts.optopt("", "quoting", "how to print file names that aren't plain text; STYLE can be: literal (the default, as they are), lossy (replace bytes that aren't UTF-8), escape (quote them like a shell would)", "STYLE");
+        opts.optflag("", "no-ignore", "don't skip files matched by .gitignore, .ignore, and .git/info/exclude when counting recursively");
         opts.optopt("", "files0-from", "read input file list from the specified file containing a NUL-terminated list of file names; use - to read from stdin", "F");
         opts.optflag("h", "help", "display this help text and exit");
         opts.optflag("v", "version", "output version information and exit");
@@ -130,7 +315,8 @@ Ditributed under the MIT license: https://opensource.org/licenses/MIT"
         let options = Self::options();
 
         // we do skip(1) here because the first argument is the program name
-        let matches = try!(options.parse(args.skip(1)));
+        let args = Arguments::new(args.skip(1));
+        let mut matches = options.parse(&args.args)?;
         if matches.opt_present("h") {
             return Err(Error::Usage);
         }
@@ -144,27 +330,119 @@ Ditributed under the MIT license: https://opensource.org/licenses/MIT"
                     // using --files0-from with FILEs is not allowed
                     return Err(Error::Files0FromWithFiles);
                 }
-                Some(try!(load_files_from(&files0_from)))
+                let files0_from = PathBuf::from(args.restore(files0_from));
+                Some((load_files_from(&files0_from)?, is_streamed(&files0_from)))
             }
             None => None,
         };
 
+        let jobs = match matches.opt_str("j") {
+            Some(jobs) => match jobs.parse() {
+                Ok(0) | Err(_) => return Err(Error::Jobs(jobs)),
+                Ok(jobs) => jobs,
+            },
+            None => pool::default_jobs(),
+        };
+
+        let mut format = match matches.opt_str("format") {
+            Some(format) => Format::from_str(&format)?,
+            None => Format::Text,
+        };
+
+        let printf = match matches.opt_str("printf") {
+            Some(printf) => {
+                if matches.opt_present("format") {
+                    return Err(Error::PrintfWithFormat);
+                }
+                format = Format::Printf;
+                Some(Template::parse(&printf).map_err(Error::Printf)?)
+            }
+            None => None,
+        };
+
+        let total = match matches.opt_str("total") {
+            Some(when) => Total::from_str(&when)?,
+            None => Total::Auto,
+        };
+
+        let max_depth = match matches.opt_str("max-depth") {
+            Some(depth) => Some(depth.parse().map_err(|_| Error::MaxDepth(depth))?),
+            None => None,
+        };
+
+        let group_by = match matches.opt_str("group-by") {
+            Some(by) => Some(GroupBy::from_str(&by)?),
+            None => None,
+        };
+
+        let on_error = match matches.opt_str("on-error") {
+            Some(policy) => Some(OnError::from_str(&policy)?),
+            None => None,
+        };
+
+        let quoting = match matches.opt_str("quoting") {
+            Some(style) => Quoting::from_str(&style)?,
+            None => Quoting::Literal,
+        };
+
+        let include: Vec<_> = matches.opt_strs("include").into_iter().map(|p| args.restore_lossy(p)).collect();
+        let exclude: Vec<_> = matches.opt_strs("exclude").into_iter().map(|p| args.restore_lossy(p)).collect();
+        let free = matches.free.drain(..).map(|file| PathBuf::from(args.restore(file))).collect();
+
         let mut opts = Options {
             bytes: matches.opt_present("c"),
             chars: matches.opt_present("m"),
             lines: matches.opt_present("l"),
             max_line: matches.opt_present("L"),
             words: matches.opt_present("w"),
-            files: files0_from.unwrap_or(matches.free),
+            recursive: matches.opt_present("r"),
+            follow_symlinks: matches.opt_present("follow-symlinks"),
+            max_depth,
+            skip_hidden: matches.opt_present("skip-hidden"),
+            no_ignore: matches.opt_present("no-ignore"),
+            filter: Filter::new(&include, &exclude),
+            read_stdin: false,
+            group_by,
+            on_error,
+            quoting,
+            decompress: matches.opt_present("decompress"),
+            archive: matches.opt_present("archive"),
+            files0_streamed: files0_from.as_ref().is_some_and(|&(_, streamed)| streamed),
+            files: files0_from.map(|(files, _)| files).unwrap_or(free),
+            jobs,
+            format,
+            printf,
+            total,
         };
 
+        // the counts a --printf template uses are counted, along with any that were asked for
+        if let Some(ref printf) = opts.printf {
+            for name in printf.metrics() {
+                match name {
+                    Lines::NAME => opts.lines = true,
+                    Words::NAME => opts.words = true,
+                    Chars::NAME => opts.chars = true,
+                    Bytes::NAME => opts.bytes = true,
+                    MaxLineLength::NAME => opts.max_line = true,
+                    _ => {}
+                }
+            }
+        }
+
         // if no options are provided, set some defaults
-        if !(opts.bytes || opts.chars || opts.lines || opts.max_line || opts.words) {
+        else if !(opts.bytes || opts.chars || opts.lines || opts.max_line || opts.words) {
             opts.lines = true;
             opts.words = true;
             opts.bytes = true;
         }
 
+        opts.read_stdin = opts.files.is_empty() && !opts.recursive;
+
+        // with no files, count the current directory when counting recursively
+        if opts.recursive && opts.files.is_empty() {
+            opts.files.push(PathBuf::from("."));
+        }
+
         Ok(opts)
     }
 
@@ -182,32 +460,114 @@ Ditributed under the MIT license: https://opensource.org/licenses/MIT"
     pub fn only_lines(&self) -> bool {
         self.lines && !(self.chars || self.max_line || self.words)
     }
+
+    /// Return `true` if the total should be printed.
+    pub fn print_total(&self) -> bool {
+        match self.total {
+            Total::Always | Total::Only => true,
+            Total::Never => false,
+            Total::Auto => match self.format {
+                Format::Json | Format::Ndjson => true,
+                _ => self.group_by.is_some() || self.files.len() > 1,
+            },
+        }
+    }
+
+    /// Return `true` if the counts for each file should be printed.
+    pub fn print_files(&self) -> bool {
+        self.total != Total::Only
+    }
+}
+
+/// The arguments to parse, with placeholders for the ones that aren't UTF-8.
+///
+/// Getopts only parses UTF-8 arguments, but file names can be anything. Each argument that isn't
+/// UTF-8 (or only the value of a `--name=value` argument) is swapped for a placeholder, and the
+/// placeholders are swapped back for the original arguments after parsing. Placeholders start with
+/// a NUL, which can't be part of a real argument.
+struct Arguments {
+    args: Vec<String>,
+    originals: Vec<OsString>,
+}
+
+impl Arguments {
+    fn new<I>(args: I) -> Arguments
+        where I: Iterator,
+        I::Item: AsRef<OsStr>,
+    {
+        let mut arguments = Arguments { args: Vec::new(), originals: Vec::new() };
+        for arg in args {
+            let arg = arg.as_ref();
+            let arg = match arg.to_str() {
+                Some(arg) => arg.to_owned(),
+                None => arguments.placeholder(arg),
+            };
+            arguments.args.push(arg);
+        }
+        arguments
+    }
+
+    fn placeholder(&mut self, arg: &OsStr) -> String {
+        let bytes = arg.as_encoded_bytes();
+        if bytes.starts_with(b"--") {
+            if let Some(equals) = bytes.iter().position(|&b| b == b'=') {
+                if let Ok(name) = str::from_utf8(&bytes[..equals]) {
+                    // SAFETY: the value is split off at an ASCII character
+                    let value = unsafe { OsStr::from_encoded_bytes_unchecked(&bytes[equals + 1..]) };
+                    return format!("{}={}", name, self.placeholder(value));
+                }
+            }
+        }
+        self.originals.push(arg.to_owned());
+        format!("\0{}", self.originals.len() - 1)
+    }
+
+    /// Return the original argument for a parsed one.
+    fn restore(&self, arg: String) -> OsString {
+        let original = arg.strip_prefix('\0')
+            .and_then(|i| i.parse().ok())
+            .and_then(|i: usize| self.originals.get(i));
+        match original {
+            Some(original) => original.clone(),
+            None => OsString::from(arg),
+        }
+    }
+
+    /// Return the original argument for a parsed one, converted to UTF-8 where it isn't.
+    fn restore_lossy(&self, arg: String) -> String {
+        self.restore(arg).to_string_lossy().into_owned()
+    }
 }
 
-fn load_files_from(file: &str) -> result::Result<Vec<String>, Error> {
-    if file == "-" {
+fn load_files_from(file: &Path) -> result::Result<Vec<PathBuf>, Error> {
+    if file == Path::new("-") {
         load_files_from_stdin()
     }
     else {
-        let file = try!(File::open(file));
+        let file = File::open(file)?;
         let reader = io::BufReader::new(file);
         load_files_from_iter(reader.bytes())
     }
 }
 
-fn load_files_from_stdin() -> result::Result<Vec<String>, Error> {
-    load_files_from_iter(io::stdin().bytes())
+/// Return `true` if a file list is something other than a regular file, or can't be stat-ed.
+fn is_streamed(file: &Path) -> bool {
+    !metadata(file).map(|metadata| metadata.is_file()).unwrap_or(false)
 }
 
-fn load_files_from_iter<I>(bytes: I) -> result::Result<Vec<String>, Error>
+fn load_files_from_stdin() -> result::Result<Vec<PathBuf>, Error> {
+    load_files_from_iter(io::BufReader::new(io::stdin()).bytes())
+}
+
+fn load_files_from_iter<I>(bytes: I) -> result::Result<Vec<PathBuf>, Error>
    where I: Iterator<Item=io::Result<u8>>
 {
     let mut vec = Vec::new();
     let mut vec_string = Vec::new();
     for b in bytes {
-        match try!(b) {
+        match b? {
             0 => {
-                vec.push(try!(String::from_utf8(vec_string.clone())));
+                vec.push(path_from_bytes(vec_string.clone())?);
                 vec_string.clear();
             }
             b => vec_string.push(b),
@@ -216,33 +576,55 @@ fn load_files_from_iter<I>(bytes: I) -> result::Result<Vec<String>, Error>
 
     // add the final string, in case it wasn't null terminated
     if vec.is_empty() || !vec_string.is_empty() {
-        vec.push(try!(String::from_utf8(vec_string)));
+        vec.push(path_from_bytes(vec_string)?);
     }
 
     Ok(vec)
 }
 
+/// Return the path for a file name from a file list. Names are just bytes on unix, elsewhere they
+/// have to be UTF-8.
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> result::Result<PathBuf, Error> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> result::Result<PathBuf, Error> {
+    Ok(PathBuf::from(String::from_utf8(bytes)?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::Options;
     use super::Error;
+    use super::Format;
+    use super::Total;
+    use super::GroupBy;
+    use super::OnError;
+    use super::Quoting;
+    use counter::Template;
+    use filter::Filter;
+    use std::path::{Path, PathBuf};
     use super::load_files_from_iter;
+    use super::is_streamed;
     use std::io;
 
     #[test]
     fn defaults() {
-        let args = vec!["test", "file"];
+        let args = ["test", "file"];
         let opts = Options::from_iter(args.iter()).unwrap();
         assert!(opts.lines);
         assert!(opts.words);
         assert!(opts.bytes);
-        assert_eq!(opts.files[0], "file");
+        assert_eq!(opts.files[0], Path::new("file"));
     }
 
     #[test]
     fn args_bytes() {
         {
-            let args = vec!["test", "-c"];
+            let args = ["test", "-c"];
             let opts = Options::from_iter(args.iter()).unwrap();
             assert!(opts.bytes);
             assert!(!opts.lines);
@@ -254,7 +636,7 @@ mod tests {
         }
 
         {
-            let args = vec!["test", "--bytes"];
+            let args = ["test", "--bytes"];
             let opts = Options::from_iter(args.iter()).unwrap();
             assert!(opts.bytes);
             assert!(!opts.lines);
@@ -268,7 +650,7 @@ mod tests {
 
     #[test]
     fn args_lines_short() {
-        let args = vec!["test", "-l"];
+        let args = ["test", "-l"];
         let opts = Options::from_iter(args.iter()).unwrap();
         assert!(!opts.bytes);
         assert!(opts.lines);
@@ -281,7 +663,7 @@ mod tests {
 
     #[test]
     fn args_lines_long() {
-        let args = vec!["test", "--lines"];
+        let args = ["test", "--lines"];
         let opts = Options::from_iter(args.iter()).unwrap();
         assert!(!opts.bytes);
         assert!(opts.lines);
@@ -294,7 +676,7 @@ mod tests {
 
     #[test]
     fn args_lines_and_bytes_short() {
-        let args = vec!["test", "-lc"];
+        let args = ["test", "-lc"];
         let opts = Options::from_iter(args.iter()).unwrap();
         assert!(opts.bytes);
         assert!(opts.lines);
@@ -307,7 +689,7 @@ mod tests {
 
     #[test]
     fn files0_from_with_files() {
-        let args = vec!["test", "--files0-from", "file", "other-file"];
+        let args = ["test", "--files0-from", "file", "other-file"];
         match Options::from_iter(args.iter()) {
             Err(Error::Files0FromWithFiles) => {} // do nothing, this error is expected
             Ok(_) => panic!("did not expect this to succeed"),
@@ -315,9 +697,264 @@ mod tests {
         }
     }
 
+    #[test]
+    fn jobs() {
+        let opts = Options::test_args(vec!["--jobs", "3"]).unwrap();
+        assert_eq!(opts.jobs, 3);
+
+        let opts = Options::test_args(vec!["-j1"]).unwrap();
+        assert_eq!(opts.jobs, 1);
+
+        let opts = Options::test_empty().unwrap();
+        assert!(opts.jobs >= 1);
+    }
+
+    #[test]
+    fn jobs_invalid() {
+        for jobs in &["0", "-2", "many"] {
+            match Options::test_args(vec!["--jobs", jobs]) {
+                Err(Error::Jobs(ref n)) if n == jobs => {} // do nothing, this error is expected
+                Ok(_) => panic!("did not expect this to succeed"),
+                Err(e) => panic!("did not expect error {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn format() {
+        let opts = Options::test_empty().unwrap();
+        assert_eq!(opts.format, Format::Text);
+
+        let opts = Options::test_args(vec!["--format=json"]).unwrap();
+        assert_eq!(opts.format, Format::Json);
+
+        let opts = Options::test_args(vec!["--format", "text"]).unwrap();
+        assert_eq!(opts.format, Format::Text);
+
+        let opts = Options::test_args(vec!["--format=ndjson"]).unwrap();
+        assert_eq!(opts.format, Format::Ndjson);
+
+        let opts = Options::test_args(vec!["--format=csv"]).unwrap();
+        assert_eq!(opts.format, Format::Csv);
+
+        let opts = Options::test_args(vec!["--format=tsv"]).unwrap();
+        assert_eq!(opts.format, Format::Tsv);
+
+        match Options::test_args(vec!["--format=xml"]) {
+            Err(Error::Format(ref f)) if f == "xml" => {} // do nothing, this error is expected
+            Ok(_) => panic!("did not expect this to succeed"),
+            Err(e) => panic!("did not expect error {}", e),
+        }
+    }
+
+    #[test]
+    fn printf() {
+        let opts = Options::test_args(vec!["--printf", "%l %n\\n"]).unwrap();
+        assert_eq!(opts.format, Format::Printf);
+        assert_eq!(opts.printf, Some(Template::parse("%l %n\\n").unwrap()));
+        // only the counts in the template are counted, so -l can still skip the words
+        assert!(opts.only_lines());
+
+        let opts = Options::test_args(vec!["-l", "--printf", "%w %L"]).unwrap();
+        assert_eq!((opts.lines, opts.words, opts.chars, opts.bytes, opts.max_line), (true, true, false, false, true));
+
+        match Options::test_args(vec!["--printf", "%q"]) {
+            Err(Error::Printf(_)) => {} // do nothing, this error is expected
+            Ok(_) => panic!("did not expect this to succeed"),
+            Err(e) => panic!("did not expect error {}", e),
+        }
+
+        match Options::test_args(vec!["--printf", "%l", "--format=json"]) {
+            Err(Error::PrintfWithFormat) => {} // do nothing, this error is expected
+            Ok(_) => panic!("did not expect this to succeed"),
+            Err(e) => panic!("did not expect error {}", e),
+        }
+    }
+
+    #[test]
+    fn total() {
+        let opts = Options::test_args(vec!["file"]).unwrap();
+        assert_eq!(opts.total, Total::Auto);
+        assert!(!opts.print_total());
+        assert!(opts.print_files());
+
+        let opts = Options::test_args(vec!["file", "file"]).unwrap();
+        assert!(opts.print_total());
+
+        let opts = Options::test_args(vec!["--format=json", "file"]).unwrap();
+        assert!(opts.print_total());
+
+        let opts = Options::test_args(vec!["--total=always", "file"]).unwrap();
+        assert_eq!(opts.total, Total::Always);
+        assert!(opts.print_total());
+        assert!(opts.print_files());
+
+        let opts = Options::test_args(vec!["--total=only", "file"]).unwrap();
+        assert_eq!(opts.total, Total::Only);
+        assert!(opts.print_total());
+        assert!(!opts.print_files());
+
+        let opts = Options::test_args(vec!["--total=never", "--format=json", "file", "file"]).unwrap();
+        assert_eq!(opts.total, Total::Never);
+        assert!(!opts.print_total());
+        assert!(opts.print_files());
+
+        match Options::test_args(vec!["--total=sometimes"]) {
+            Err(Error::Total(ref when)) if when == "sometimes" => {} // do nothing, this error is expected
+            Ok(_) => panic!("did not expect this to succeed"),
+            Err(e) => panic!("did not expect error {}", e),
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let opts = Options::test_empty().unwrap();
+        assert!(opts.filter.is_empty());
+        assert!(opts.read_stdin);
+
+        let opts = Options::test_args(vec!["--include", "*.rs", "--include=*.toml", "--exclude", "tests/**", "src"]).unwrap();
+        assert_eq!(opts.filter, Filter::new(&["*.rs", "*.toml"], &["tests/**"]));
+        assert!(!opts.read_stdin);
+    }
+
+    #[test]
+    fn group_by() {
+        let opts = Options::test_empty().unwrap();
+        assert_eq!(opts.group_by, None);
+        assert!(!opts.print_total());
+
+        let opts = Options::test_args(vec!["--group-by", "ext"]).unwrap();
+        assert_eq!(opts.group_by, Some(GroupBy::Ext));
+        assert!(opts.print_total());
+
+        let opts = Options::test_args(vec!["--group-by=dir"]).unwrap();
+        assert_eq!(opts.group_by, Some(GroupBy::Dir));
+
+        let opts = Options::test_args(vec!["--group-by=depth=2"]).unwrap();
+        assert_eq!(opts.group_by, Some(GroupBy::Depth(2)));
+
+        for by in &["size", "depth=0", "depth=x", "depth"] {
+            match Options::test_args(vec!["--group-by", by]) {
+                Err(Error::GroupBy(ref b)) if b == by => {} // do nothing, this error is expected
+                Ok(_) => panic!("did not expect this to succeed"),
+                Err(e) => panic!("did not expect error {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn quoting() {
+        let opts = Options::test_empty().unwrap();
+        assert_eq!(opts.quoting, Quoting::Literal);
+
+        let opts = Options::test_args(vec!["--quoting", "lossy"]).unwrap();
+        assert_eq!(opts.quoting, Quoting::Lossy);
+
+        let opts = Options::test_args(vec!["--quoting=escape"]).unwrap();
+        assert_eq!(opts.quoting, Quoting::Escape);
+
+        match Options::test_args(vec!["--quoting", "c"]) {
+            Err(Error::Quoting(ref q)) if q == "c" => {} // do nothing, this error is expected
+            Ok(_) => panic!("did not expect this to succeed"),
+            Err(e) => panic!("did not expect error {}", e),
+        }
+    }
+
+    /// File names that aren't UTF-8 are kept as they are, even in option values.
+    #[cfg(unix)]
+    #[test]
+    fn latin1() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"caf\xE9.txt");
+        let args = [OsStr::new("wc"), OsStr::new("-l"), name, OsStr::new("b")];
+        let opts = Options::from_iter(args.iter()).unwrap();
+        assert_eq!(opts.files, vec![Path::new(name), Path::new("b")]);
+
+        let include = b"--include=*\xE9.txt".to_vec();
+        let args = [OsStr::new("wc"), OsStr::from_bytes(&include), name];
+        let opts = Options::from_iter(args.iter()).unwrap();
+        assert!(opts.filter.is_match("caf\u{FFFD}.txt"));
+        assert_eq!(opts.files, vec![Path::new(name)]);
+
+        let vec = load_files_from_iter(b"caf\xE9.txt\0b".iter().map(|b| Ok(*b))).unwrap();
+        assert_eq!(vec, vec![Path::new(name), Path::new("b")]);
+    }
+
+    #[test]
+    fn on_error() {
+        let opts = Options::test_empty().unwrap();
+        assert_eq!(opts.on_error, None);
+
+        let opts = Options::test_args(vec!["--on-error", "skip"]).unwrap();
+        assert_eq!(opts.on_error, Some(OnError::Skip));
+
+        let opts = Options::test_args(vec!["--on-error=zero"]).unwrap();
+        assert_eq!(opts.on_error, Some(OnError::Zero));
+
+        let opts = Options::test_args(vec!["--on-error=abort"]).unwrap();
+        assert_eq!(opts.on_error, Some(OnError::Abort));
+
+        match Options::test_args(vec!["--on-error", "ignore"]) {
+            Err(Error::OnError(ref p)) if p == "ignore" => {} // do nothing, this error is expected
+            Ok(_) => panic!("did not expect this to succeed"),
+            Err(e) => panic!("did not expect error {}", e),
+        }
+    }
+
+    #[test]
+    fn decompress() {
+        let opts = Options::test_empty().unwrap();
+        assert!(!opts.decompress);
+
+        let opts = Options::test_args(vec!["--decompress", "logs.gz"]).unwrap();
+        assert!(opts.decompress);
+        assert_eq!(opts.files, vec![PathBuf::from("logs.gz")]);
+    }
+
+    #[test]
+    fn archive() {
+        let opts = Options::test_empty().unwrap();
+        assert!(!opts.archive);
+
+        let opts = Options::test_args(vec!["--archive", "release.tar.gz"]).unwrap();
+        assert!(opts.archive);
+        assert_eq!(opts.files, vec![PathBuf::from("release.tar.gz")]);
+    }
+
+    #[test]
+    fn recursive() {
+        let opts = Options::test_empty().unwrap();
+        assert!(!opts.recursive);
+        assert!(opts.files.is_empty());
+
+        let opts = Options::test_args(vec!["-r"]).unwrap();
+        assert!(opts.recursive);
+        assert!(!opts.follow_symlinks);
+        assert!(!opts.skip_hidden);
+        assert!(!opts.no_ignore);
+        assert_eq!(opts.max_depth, None);
+        assert_eq!(opts.files, vec![PathBuf::from(".")]);
+
+        let opts = Options::test_args(vec!["--recursive", "--follow-symlinks", "--skip-hidden", "--no-ignore", "--max-depth=3", "src"]).unwrap();
+        assert!(opts.recursive);
+        assert!(opts.follow_symlinks);
+        assert!(opts.skip_hidden);
+        assert!(opts.no_ignore);
+        assert_eq!(opts.max_depth, Some(3));
+        assert_eq!(opts.files, vec![PathBuf::from("src")]);
+
+        match Options::test_args(vec!["--max-depth=-1"]) {
+            Err(Error::MaxDepth(ref n)) if n == "-1" => {} // do nothing, this error is expected
+            Ok(_) => panic!("did not expect this to succeed"),
+            Err(e) => panic!("did not expect error {}", e),
+        }
+    }
+
     fn vec_from_string(s: &str) -> Vec<io::Result<u8>> {
         s.bytes()
-            .map(|c| Ok(c))
+            .map(Ok)
             .collect()
     }
 
@@ -325,31 +962,41 @@ mod tests {
     fn files0_from_no_null_term() {
         let vec = load_files_from_iter(vec_from_string("a\0b\0c").into_iter()).unwrap();
         assert_eq!(vec.len(), 3);
-        assert_eq!(vec[0], "a");
-        assert_eq!(vec[1], "b");
-        assert_eq!(vec[2], "c");
+        assert_eq!(vec[0], Path::new("a"));
+        assert_eq!(vec[1], Path::new("b"));
+        assert_eq!(vec[2], Path::new("c"));
     }
 
     #[test]
     fn files0_from_one_item() {
         let vec = load_files_from_iter(vec_from_string("one").into_iter()).unwrap();
         assert_eq!(vec.len(), 1);
-        assert_eq!(vec[0], "one");
+        assert_eq!(vec[0], Path::new("one"));
     }
 
     #[test]
     fn files0_from_null_term() {
         let vec = load_files_from_iter(vec_from_string("a\0b\0c\0").into_iter()).unwrap();
         assert_eq!(vec.len(), 3);
-        assert_eq!(vec[0], "a");
-        assert_eq!(vec[1], "b");
-        assert_eq!(vec[2], "c");
+        assert_eq!(vec[0], Path::new("a"));
+        assert_eq!(vec[1], Path::new("b"));
+        assert_eq!(vec[2], Path::new("c"));
+    }
+
+    #[test]
+    fn files0_streamed() {
+        let opts = Options::test_args(vec!["--files0-from", "Cargo.toml"]).unwrap();
+        assert!(!opts.files0_streamed);
+        let opts = Options::test_args(vec!["Cargo.toml"]).unwrap();
+        assert!(!opts.files0_streamed);
+        assert!(is_streamed(Path::new("src")));
+        assert!(is_streamed(Path::new("missing")));
     }
 
     #[test]
     fn files0_from_empty() {
         let vec = load_files_from_iter(vec_from_string("").into_iter()).unwrap();
         assert_eq!(vec.len(), 1);
-        assert_eq!(vec[0], "");
+        assert_eq!(vec[0], Path::new(""));
     }
 }
@@ -0,0 +1,173 @@
+//! A small worker pool that processes items in parallel but hands back the results in order.
+//!
+//! Pools share a `Budget` of jobs, so a pool started by the work of another pool (like the chunks
+//! of a large file while counting several files) only uses the jobs that are left over.
+
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Return the default number of jobs to run in parallel, the number of CPUs.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// The number of jobs that can run at once.
+#[derive(Debug)]
+pub struct Budget {
+    jobs: usize,
+    /// The jobs that aren't running, not counting the thread that created the budget.
+    spare: AtomicUsize,
+}
+
+impl Budget {
+    /// Create a budget of `jobs` jobs. The calling thread is one of them.
+    pub fn new(jobs: usize) -> Budget {
+        let jobs = max(jobs, 1);
+        Budget { jobs, spare: AtomicUsize::new(jobs - 1) }
+    }
+
+    /// The number of jobs in the budget, whether they are running or not.
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Take up to `wanted` of the spare jobs, and return how many were taken.
+    fn take(&self, wanted: usize) -> usize {
+        let spare = self.spare
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |spare| Some(spare - min(spare, wanted)))
+            .unwrap_or_else(|spare| spare);
+        min(spare, wanted)
+    }
+
+    /// Give back jobs taken with `take()`.
+    fn give_back(&self, jobs: usize) {
+        self.spare.fetch_add(jobs, Ordering::AcqRel);
+    }
+}
+
+/// Call `work` for each of the given items, using the calling thread and as many of the spare jobs
+/// in `budget` as there are items for. The result for each item is passed to `done` (on the
+/// calling thread) in the same order as `items`, as soon as it and all of the results before it are
+/// ready.
+///
+/// The jobs are given back to the budget as soon as there are no items left for them, so `work`
+/// can start pools of its own with the same budget.
+pub fn ordered<T, R, W, D>(items: &[T], budget: &Budget, work: W, mut done: D)
+    where T: Sync,
+          R: Send,
+          W: Fn(&T) -> R + Sync,
+          D: FnMut(&T, R),
+{
+    let spare = if items.len() <= 1 { 0 } else { budget.take(items.len() - 1) };
+    if spare == 0 {
+        for item in items {
+            done(item, work(item));
+        }
+        return;
+    }
+
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+    thread::scope(|scope| {
+        // the calling thread's job goes to the last worker while the calling thread waits for
+        // results, so that one isn't given back
+        for worker in 0..=spare {
+            let tx = tx.clone();
+            let next = &next;
+            let work = &work;
+            scope.spawn(move || {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= items.len() {
+                        break;
+                    }
+                    if tx.send((i, work(&items[i]))).is_err() {
+                        break;
+                    }
+                }
+                if worker < spare {
+                    budget.give_back(1);
+                }
+            });
+        }
+        drop(tx);
+
+        // results can arrive out of order, hold on to them until it is their turn
+        let mut pending = BTreeMap::new();
+        let mut expected = 0;
+        for (i, result) in rx {
+            pending.insert(i, result);
+            while let Some(result) = pending.remove(&expected) {
+                done(&items[expected], result);
+                expected += 1;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ordered, Budget};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn in_order() {
+        let items: Vec<u64> = (0..100).collect();
+        let mut results = Vec::new();
+        ordered(&items, &Budget::new(8), |i| {
+            // make the early items finish last
+            thread::sleep(Duration::from_millis(100 - i));
+            i * 2
+        }, |i, r| results.push((*i, r)));
+
+        assert_eq!(results, items.iter().map(|i| (*i, i * 2)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn single_job() {
+        let items = vec!["a", "b", "c"];
+        let mut results = Vec::new();
+        ordered(&items, &Budget::new(1), |s| s.to_uppercase(), |_, r| results.push(r));
+        assert_eq!(results, vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn empty() {
+        let items: Vec<u64> = Vec::new();
+        ordered(&items, &Budget::new(4), |i| *i, |_, _| panic!("there are no items"));
+    }
+
+    /// Pools started by the work of another pool share its budget, so no more than the budgeted
+    /// number of jobs run at once, and all of the jobs are given back at the end.
+    #[test]
+    fn nested() {
+        let budget = Budget::new(4);
+        let running = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        let items: Vec<u64> = (0..6).collect();
+        let mut results = Vec::new();
+        ordered(&items, &budget, |i| {
+            let mut sum = 0;
+            ordered(&items, &budget, |j| {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(5));
+                running.fetch_sub(1, Ordering::SeqCst);
+                i * j
+            }, |_, r| sum += r);
+            sum
+        }, |_, r| results.push(r));
+
+        assert_eq!(results, items.iter().map(|i| i * 15).collect::<Vec<_>>());
+        let most = most.load(Ordering::SeqCst);
+        assert!(most <= 4, "{} jobs ran at once", most);
+        assert_eq!(budget.take(10), 3);
+    }
+}
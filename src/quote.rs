@@ -0,0 +1,160 @@
+//! Printing file names that aren't plain text, for `--quoting`.
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use options::Quoting;
+
+/// Return a file name the way it is printed in text output. With `Literal` that is the name as it
+/// is, like GNU wc, unless it has a newline which would break up the row, so it is escaped.
+pub fn quote_bytes(name: &OsStr, quoting: Quoting) -> Cow<'_, [u8]> {
+    let bytes = name.as_encoded_bytes();
+    match quoting {
+        Quoting::Literal if !bytes.contains(&b'\n') => Cow::Borrowed(bytes),
+        Quoting::Lossy => match name.to_string_lossy() {
+            Cow::Borrowed(name) => Cow::Borrowed(name.as_bytes()),
+            Cow::Owned(name) => Cow::Owned(name.into_bytes()),
+        },
+        _ => Cow::Owned(escape(name).into_bytes()),
+    }
+}
+
+/// Return a file name the way it is printed in output that has to be UTF-8, like JSON and CSV.
+/// `Literal` names get the lossy conversion.
+pub fn quote(name: &OsStr, quoting: Quoting) -> Cow<'_, str> {
+    match quoting {
+        Quoting::Literal | Quoting::Lossy => name.to_string_lossy(),
+        Quoting::Escape => Cow::Owned(escape(name)),
+    }
+}
+
+/// Return a file name quoted the way GNU tools quote names in messages, so it can be pasted into
+/// a shell.
+///
+/// Names that only have letters, digits, and punctuation the shell doesn't care about are left as
+/// they are. Anything else is put in single quotes, or in double quotes if the only problem is a
+/// `'`. Control characters and bytes that aren't UTF-8 are written as `$'\ooo'` escapes, so
+/// `a<0xE9>b` becomes `'a'$'\351''b'`.
+pub fn escape(name: &OsStr) -> String {
+    let bytes = name.as_encoded_bytes();
+    if let Ok(name) = ::std::str::from_utf8(bytes) {
+        let mut chars = name.chars();
+        if chars.next().is_some_and(|c| is_safe(c, true)) && chars.all(|c| is_safe(c, false)) {
+            return name.to_owned();
+        }
+        if name.contains('\'') && !name.contains(|c: char| c.is_control() || "\"$`\\!".contains(c)) {
+            return format!("\"{}\"", name);
+        }
+    }
+
+    let mut quoted = String::from("'");
+    let mut escaping = false;
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c.is_control() {
+                if !escaping {
+                    quoted.push_str("'$'");
+                    escaping = true;
+                }
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    push_escape(&mut quoted, byte);
+                }
+                continue;
+            }
+            if escaping {
+                quoted.push_str("''");
+                escaping = false;
+            }
+            if c == '\'' { quoted.push_str("'\\''"); }
+            else { quoted.push(c); }
+        }
+        if !chunk.invalid().is_empty() && !escaping {
+            quoted.push_str("'$'");
+            escaping = true;
+        }
+        for &byte in chunk.invalid() {
+            push_escape(&mut quoted, byte);
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Return `true` if the character doesn't need quoting. `#` and `~` only matter at the start.
+fn is_safe(c: char, first: bool) -> bool {
+    c.is_ascii_alphanumeric()
+        || "%+,-./:@_".contains(c)
+        || (!first && "#~".contains(c))
+        || (!c.is_ascii() && !c.is_control())
+}
+
+/// Write the escape for a byte inside of `$'...'`.
+fn push_escape(quoted: &mut String, byte: u8) {
+    match byte {
+        b'\x07' => quoted.push_str("\\a"),
+        b'\x08' => quoted.push_str("\\b"),
+        b'\t' => quoted.push_str("\\t"),
+        b'\n' => quoted.push_str("\\n"),
+        b'\x0B' => quoted.push_str("\\v"),
+        b'\x0C' => quoted.push_str("\\f"),
+        b'\r' => quoted.push_str("\\r"),
+        _ => quoted.push_str(&format!("\\{:03o}", byte)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, quote, quote_bytes};
+    use options::Quoting;
+    use std::ffi::OsStr;
+
+    /// A name with a Latin-1 `é`, which isn't valid UTF-8.
+    #[cfg(unix)]
+    fn latin1() -> &'static OsStr {
+        use std::os::unix::ffi::OsStrExt;
+        OsStr::from_bytes(b"caf\xE9.txt")
+    }
+
+    #[test]
+    fn escape_plain() {
+        assert_eq!(escape(OsStr::new("src/main.rs")), "src/main.rs");
+        assert_eq!(escape(OsStr::new("a~b#c")), "a~b#c");
+        assert_eq!(escape(OsStr::new("café")), "café");
+        assert_eq!(escape(OsStr::new("~file")), "'~file'");
+        assert_eq!(escape(OsStr::new("")), "''");
+    }
+
+    #[test]
+    fn escape_quotes() {
+        assert_eq!(escape(OsStr::new("standard input")), "'standard input'");
+        assert_eq!(escape(OsStr::new("it's")), "\"it's\"");
+        assert_eq!(escape(OsStr::new("it's $5")), "'it'\\''s $5'");
+    }
+
+    #[test]
+    fn escape_control() {
+        assert_eq!(escape(OsStr::new("a\nb")), "'a'$'\\n''b'");
+        assert_eq!(escape(OsStr::new("\n")), "''$'\\n'");
+        assert_eq!(escape(OsStr::new("a\x01\tb")), "'a'$'\\001\\t''b'");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escape_latin1() {
+        assert_eq!(escape(latin1()), "'caf'$'\\351''.txt'");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quoting() {
+        assert_eq!(&*quote_bytes(latin1(), Quoting::Literal), b"caf\xE9.txt");
+        assert_eq!(&*quote_bytes(latin1(), Quoting::Lossy), "caf\u{FFFD}.txt".as_bytes());
+        assert_eq!(&*quote_bytes(latin1(), Quoting::Escape), b"'caf'$'\\351''.txt'");
+        assert_eq!(&*quote_bytes(OsStr::new("a\nb"), Quoting::Literal), b"'a'$'\\n''b'");
+
+        assert_eq!(quote(latin1(), Quoting::Literal), "caf\u{FFFD}.txt");
+        assert_eq!(quote(latin1(), Quoting::Lossy), "caf\u{FFFD}.txt");
+        assert_eq!(quote(latin1(), Quoting::Escape), "'caf'$'\\351''.txt'");
+        assert_eq!(quote(OsStr::new("a b"), Quoting::Literal), "a b");
+    }
+}
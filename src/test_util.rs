@@ -0,0 +1,49 @@
+//! Fixtures shared by the tests of several modules.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+/// A directory tree for tests that is removed when dropped.
+pub struct Tree {
+    pub root: PathBuf,
+}
+
+impl Tree {
+    /// Create a tree with the given files, directories are created as needed. Names ending in
+    /// `/` are created as empty directories.
+    pub fn new(name: &str, files: &[&str]) -> Tree {
+        let root = env::temp_dir().join(format!("rust-wc-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            if file.ends_with('/') {
+                fs::create_dir_all(&path).unwrap();
+            }
+            else {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, file.as_bytes()).unwrap();
+            }
+        }
+        Tree { root }
+    }
+
+    pub fn path(&self, file: &str) -> String {
+        self.root.join(file).to_str().unwrap().to_owned()
+    }
+
+    /// Return the given files relative to the root of the tree.
+    pub fn relative(&self, files: &[PathBuf]) -> Vec<String> {
+        let root = self.path("");
+        files.iter()
+            .map(|f| f.to_str().unwrap().trim_start_matches(&root).to_owned())
+            .collect()
+    }
+}
+
+impl Drop for Tree {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
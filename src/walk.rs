@@ -0,0 +1,292 @@
+//! Recursive directory traversal for `--recursive`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use ignore;
+use options::Options;
+
+/// A problem found while walking a directory.
+#[derive(Debug)]
+pub struct Error {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+/// The result of expanding a list of files.
+#[derive(Debug, Default)]
+pub struct Walked {
+    /// The files to count, in the order they were found.
+    pub files: Vec<PathBuf>,
+    /// Directories and entries that couldn't be read.
+    pub errors: Vec<Error>,
+}
+
+/// Expand the given list of files by replacing each directory with the regular files inside of it
+/// and its subdirectories. Everything else is kept as is, so problems with those are reported when
+/// they are counted.
+///
+/// Directory entries are visited in name order. Symbolic links inside of a directory are skipped
+/// unless `opts.follow_symlinks` is set, `opts.max_depth` limits how many levels below each
+/// argument are visited, and `opts.skip_hidden` skips entries that start with a `.`. Files that
+/// don't pass `opts.filter` are left out, including arguments that aren't directories.
+///
+/// Unless `opts.no_ignore` is set, entries matched by `.gitignore`, `.ignore`, and
+/// `.git/info/exclude` are skipped along with `.git` directories. The arguments themselves are
+/// never skipped.
+pub fn expand<P: AsRef<Path>>(files: &[P], opts: &Options) -> Walked {
+    let mut walked = Walked::default();
+    for file in files {
+        let file = file.as_ref();
+        // arguments are always followed, they were named explicitly
+        match fs::metadata(file) {
+            Ok(ref metadata) if metadata.is_dir() => {
+                let mut ignores = if opts.no_ignore { ignore::Stack::default() }
+                    else { ignore::Stack::new(file).unwrap_or_else(|error| {
+                        walked.errors.push(Error { path: file.to_owned(), error });
+                        ignore::Stack::default()
+                    }) };
+                let mut ancestors = Vec::new();
+                walk(file, file, metadata, opts, &mut ancestors, &mut ignores, &mut walked);
+            }
+            _ => if file == Path::new("-") || opts.filter.is_match(&file.to_string_lossy()) {
+                walked.files.push(file.to_owned());
+            },
+        }
+    }
+    walked
+}
+
+/// An identifier for a directory, used to detect symbolic link loops.
+#[cfg(unix)]
+fn id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn id(_: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Walk `dir`, which is `root` or a directory below it. Filters are matched against paths
+/// relative to `root`, and its depth is counted from there.
+fn walk(root: &Path, dir: &Path, metadata: &fs::Metadata, opts: &Options,
+        ancestors: &mut Vec<(u64, u64)>, ignores: &mut ignore::Stack, walked: &mut Walked)
+{
+    let depth = relative(dir, root).components().count() + 1;
+    if opts.max_depth.is_some_and(|max| depth > max) {
+        return;
+    }
+
+    let dir_id = id(metadata);
+    if let Some(dir_id) = dir_id {
+        if ancestors.contains(&dir_id) {
+            walked.errors.push(Error {
+                path: dir.to_owned(),
+                error: io::Error::other("file system loop detected"),
+            });
+            return;
+        }
+        ancestors.push(dir_id);
+    }
+
+    let mut entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) => {
+            walked.errors.push(Error { path: dir.to_owned(), error });
+            if dir_id.is_some() { ancestors.pop(); }
+            return;
+        }
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+
+    if !opts.no_ignore {
+        if let Err(error) = ignores.push(dir) {
+            walked.errors.push(Error { path: dir.to_owned(), error });
+        }
+    }
+
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name();
+        if opts.skip_hidden && name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        let metadata = match entry.file_type() {
+            Ok(ref file_type) if file_type.is_symlink() && !opts.follow_symlinks => continue,
+            Ok(ref file_type) if file_type.is_symlink() => fs::metadata(&path),
+            Ok(_) => entry.metadata(),
+            Err(e) => Err(e),
+        };
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(error) => {
+                walked.errors.push(Error { path: path.clone(), error });
+                continue;
+            }
+        };
+
+        if !opts.no_ignore && (metadata.is_dir() && name == ".git" || ignores.is_ignored(&path, metadata.is_dir())) {
+            continue;
+        }
+
+        let below = relative(&path, root).to_string_lossy();
+        if metadata.is_dir() {
+            if !opts.filter.is_excluded_below(&path.to_string_lossy(), &below) {
+                walk(root, &path, &metadata, opts, ancestors, ignores, walked);
+            }
+        }
+        else if metadata.is_file() && opts.filter.is_match_below(&path.to_string_lossy(), &below) {
+            walked.files.push(path);
+        }
+        // everything else (sockets, fifos, devices) is skipped
+    }
+
+    if !opts.no_ignore { ignores.pop(); }
+    if dir_id.is_some() { ancestors.pop(); }
+}
+
+/// Return `path` relative to `root`, which it is inside of.
+fn relative<'p>(path: &'p Path, root: &Path) -> &'p Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+fn read_dir(dir: &Path) -> io::Result<Vec<fs::DirEntry>> {
+    fs::read_dir(dir)?.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand;
+    use options::Options;
+    use std::fs;
+    use std::path::PathBuf;
+    use test_util::Tree;
+
+    const FILES: &[&str] = &["b.txt", "a/z.rs", "a/b/c.rs", ".hidden/x", "a/.y", "empty/"];
+
+    #[test]
+    fn everything() {
+        let tree = Tree::new("walk-everything", FILES);
+        let opts = Options::test_args(vec!["-r"]).unwrap();
+        let walked = expand(&[tree.path("")], &opts);
+        assert!(walked.errors.is_empty());
+        assert_eq!(tree.relative(&walked.files), vec![".hidden/x", "a/.y", "a/b/c.rs", "a/z.rs", "b.txt"]);
+    }
+
+    #[test]
+    fn skip_hidden() {
+        let tree = Tree::new("walk-hidden", FILES);
+        let opts = Options::test_args(vec!["-r", "--skip-hidden"]).unwrap();
+        let walked = expand(&[tree.path("")], &opts);
+        assert_eq!(tree.relative(&walked.files), vec!["a/b/c.rs", "a/z.rs", "b.txt"]);
+    }
+
+    #[test]
+    fn max_depth() {
+        let tree = Tree::new("walk-depth", FILES);
+        let opts = Options::test_args(vec!["-r", "--max-depth", "1"]).unwrap();
+        let walked = expand(&[tree.path("")], &opts);
+        assert_eq!(tree.relative(&walked.files), vec!["b.txt"]);
+
+        let opts = Options::test_args(vec!["-r", "--max-depth", "2"]).unwrap();
+        let walked = expand(&[tree.path("")], &opts);
+        assert_eq!(tree.relative(&walked.files), vec![".hidden/x", "a/.y", "a/z.rs", "b.txt"]);
+    }
+
+    #[test]
+    fn files_kept() {
+        let tree = Tree::new("walk-files", FILES);
+        let opts = Options::test_args(vec!["-r"]).unwrap();
+        let files = vec![tree.path("b.txt"), "missing".to_owned(), "-".to_owned(), tree.path("a/b")];
+        let walked = expand(&files, &opts);
+        assert_eq!(tree.relative(&walked.files), vec!["b.txt", "missing", "-", "a/b/c.rs"]);
+    }
+
+    #[test]
+    fn ignore_files() {
+        let tree = Tree::new("walk-ignore", &[".git/HEAD", ".gitignore", "a.log", "keep.log", "b.txt",
+                                               "target/x", "src/.ignore", "src/gen.rs", "src/lib.rs"]);
+        fs::write(tree.path(".gitignore"), "*.log\n!keep.log\ntarget/\n").unwrap();
+        fs::write(tree.path("src/.ignore"), "gen.rs\n").unwrap();
+
+        let opts = Options::test_args(vec!["-r"]).unwrap();
+        let walked = expand(&[tree.path("")], &opts);
+        assert!(walked.errors.is_empty());
+        assert_eq!(tree.relative(&walked.files), vec![".gitignore", "b.txt", "keep.log", "src/.ignore", "src/lib.rs"]);
+
+        // rules from the directories above an argument still apply
+        let walked = expand(&[tree.path("src")], &opts);
+        assert_eq!(tree.relative(&walked.files), vec!["src/.ignore", "src/lib.rs"]);
+
+        // arguments are never ignored
+        let walked = expand(&[tree.path("target"), tree.path("a.log")], &opts);
+        assert_eq!(tree.relative(&walked.files), vec!["target/x", "a.log"]);
+
+        let opts = Options::test_args(vec!["-r", "--no-ignore"]).unwrap();
+        let walked = expand(&[tree.path("")], &opts);
+        assert_eq!(tree.relative(&walked.files), vec![".git/HEAD", ".gitignore", "a.log", "b.txt", "keep.log",
+                                                      "src/.ignore", "src/gen.rs", "src/lib.rs", "target/x"]);
+    }
+
+    #[test]
+    fn filter() {
+        let tree = Tree::new("walk-filter", &["src/main.rs", "src/gen/parser.rs", "tests/it.rs", "README.md", "target/x.rs"]);
+        let opts = Options::test_args(vec!["-r", "--include", "*.rs", "--exclude", "target", "--exclude", "src/gen/**"]).unwrap();
+        let walked = expand(&[tree.path("")], &opts);
+        assert_eq!(tree.relative(&walked.files), vec!["src/main.rs", "tests/it.rs"]);
+
+        // arguments that aren't directories are filtered too
+        let walked = expand(&[tree.path("README.md"), tree.path("tests"), "-".to_owned()], &opts);
+        assert_eq!(tree.relative(&walked.files), vec!["tests/it.rs", "-"]);
+    }
+
+    /// Patterns with a `/` only see the part of the path below the directory being walked.
+    #[test]
+    fn filter_below_root() {
+        let tree = Tree::new("walk-filter-root", &["tests/dir/a.rs", "tests/dir/tests/b.rs", "tests/dir/sub/c.rs"]);
+        let opts = Options::test_args(vec!["-r", "--exclude", "tests/**"]).unwrap();
+        let walked = expand(&[tree.path("tests/dir")], &opts);
+        assert_eq!(tree.relative(&walked.files), vec!["tests/dir/a.rs", "tests/dir/sub/c.rs"]);
+    }
+
+    /// File names that aren't UTF-8 are kept as they are.
+    #[cfg(unix)]
+    #[test]
+    fn latin1() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let tree = Tree::new("walk-latin1", &["a.txt"]);
+        let name = OsStr::from_bytes(b"caf\xE9.txt");
+        fs::write(tree.root.join(name), "").unwrap();
+
+        let opts = Options::test_args(vec!["-r", "--include", "*.txt"]).unwrap();
+        let walked = expand(&[tree.path("")], &opts);
+        assert!(walked.errors.is_empty());
+        assert_eq!(walked.files, vec![tree.root.join("a.txt"), tree.root.join(name)]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let tree = Tree::new("walk-symlinks", &["dir/file"]);
+        symlink(tree.path("dir/file"), tree.path("dir/link")).unwrap();
+        symlink(tree.path("dir"), tree.path("dir/loop")).unwrap();
+
+        let opts = Options::test_args(vec!["-r"]).unwrap();
+        let walked = expand(&[tree.path("dir")], &opts);
+        assert!(walked.errors.is_empty());
+        assert_eq!(tree.relative(&walked.files), vec!["dir/file"]);
+
+        let opts = Options::test_args(vec!["-r", "--follow-symlinks"]).unwrap();
+        let walked = expand(&[tree.path("dir")], &opts);
+        assert_eq!(tree.relative(&walked.files), vec!["dir/file", "dir/link"]);
+        assert_eq!(walked.errors.len(), 1);
+        assert_eq!(walked.errors[0].path, PathBuf::from(tree.path("dir/loop")));
+    }
+}
@@ -0,0 +1,67 @@
+//! Tests for `--archive` output with the whole program, where the subtotal for an archive has to
+//! be told apart from the files in it.
+
+extern crate tar;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+/// Run wc in a directory with `t.tar`, which has the files `a` and `b`.
+fn wc(name: &str, args: &[&str]) -> String {
+    let dir: PathBuf = env::temp_dir().join(format!("rust-wc-archive-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    let mut builder = tar::Builder::new(Vec::new());
+    for &(path, text) in &[("a", "one\n"), ("b", "two words\n")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(text.len() as u64);
+        builder.append_data(&mut header, path, text.as_bytes()).unwrap();
+    }
+    fs::write(dir.join("t.tar"), builder.into_inner().unwrap()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-wc"))
+        .args(["--archive", "-l"])
+        .args(args)
+        .arg("t.tar")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn json() {
+    assert_eq!(wc("json", &["--format=json"]), concat!(
+        "{\"files\":[\n",
+        "{\"file\":\"t.tar:a\",\"lines\":1},\n",
+        "{\"file\":\"t.tar:b\",\"lines\":1}\n",
+        "],\"subtotals\":[\n",
+        "{\"archive\":\"t.tar\",\"lines\":2}\n",
+        "],\"total\":{\"lines\":2}}\n",
+    ));
+}
+
+#[test]
+fn ndjson() {
+    assert_eq!(wc("ndjson", &["--format=ndjson"]), concat!(
+        "{\"type\":\"file\",\"file\":\"t.tar:a\",\"lines\":1}\n",
+        "{\"type\":\"file\",\"file\":\"t.tar:b\",\"lines\":1}\n",
+        "{\"type\":\"subtotal\",\"archive\":\"t.tar\",\"lines\":2}\n",
+        "{\"type\":\"total\",\"lines\":2}\n",
+    ));
+}
+
+#[test]
+fn csv() {
+    assert_eq!(wc("csv", &["--format=csv"]),
+               "lines,file,error\n1,t.tar:a,\n1,t.tar:b,\n2,t.tar (subtotal),\n");
+}
+
+#[test]
+fn tsv() {
+    assert_eq!(wc("tsv", &["--format=tsv"]),
+               "lines\tfile\terror\n1\tt.tar:a\t\n1\tt.tar:b\t\n2\tt.tar (subtotal)\t\n");
+}
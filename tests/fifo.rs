@@ -0,0 +1,69 @@
+//! Tests for counting named pipes with the whole program, which reads them through the same
+//! functions as regular files.
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long wc gets to count the FIFO before the test fails. A second open of the FIFO waits for
+/// another writer, so it would never return.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run wc on a FIFO that is written once with two lines.
+fn wc(name: &str, args: &[&str]) -> Output {
+    let dir: PathBuf = env::temp_dir().join(format!("rust-wc-fifo-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    let fifo = dir.join("fifo");
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust-wc"))
+        .args(args)
+        .arg("fifo")
+        .current_dir(&dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let writer = {
+        let fifo = fifo.clone();
+        thread::spawn(move || fs::write(fifo, "one\ntwo\n").unwrap())
+    };
+
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > TIMEOUT {
+            child.kill().unwrap();
+            panic!("wc {:?} didn't finish counting the FIFO", args);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    writer.join().unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+#[test]
+fn counts() {
+    let output = wc("counts", &[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "      2       2       8 fifo\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn lines() {
+    let output = wc("lines", &["-l"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2 fifo\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn bytes() {
+    let output = wc("bytes", &["-c"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "8 fifo\n");
+    assert!(output.status.success());
+}
@@ -0,0 +1,52 @@
+//! Tests for `--on-error` that need the whole program, like the exit status and the summary.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command, Output};
+
+/// Run wc in a directory with the files `a` and `b`, which have one line each.
+fn wc(name: &str, args: &[&str]) -> Output {
+    let dir: PathBuf = env::temp_dir().join(format!("rust-wc-cli-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a"), "one\n").unwrap();
+    fs::write(dir.join("b"), "two words\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-wc"))
+        .args(args)
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+/// Abort stops at the first failure, but the files before it, the total, and the summary are
+/// still printed.
+#[test]
+fn abort() {
+    let output = wc("abort", &["--on-error=abort", "-j1", "a", "missing", "b"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), " 1  1  4 a\n 1  1  4 total\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr),
+               "wc: missing: No such file or directory\nwc: 1 file couldn't be counted\n");
+}
+
+/// JSON output is still closed after an abort.
+#[test]
+fn abort_json() {
+    let output = wc("abort-json", &["--on-error=abort", "--format=json", "-j1", "missing", "a"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("{\"files\":["), "{}", stdout);
+    assert!(stdout.ends_with("}\n"), "{}", stdout);
+    assert!(!stdout.contains("\"a\""), "{}", stdout);
+}
+
+#[test]
+fn skip() {
+    let output = wc("skip", &["--on-error=skip", "a", "missing", "b"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), " 1  1  4 a\n 1  2 10 b\n 2  3 14 total\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr),
+               "wc: missing: No such file or directory\nwc: 1 file couldn't be counted\n");
+}